@@ -0,0 +1,239 @@
+//! A blocking client, for applications that don't run an async runtime (e.g. CLI tools or
+//! synchronous web frameworks).
+//!
+//! The [`BlockingJwksClient`] drives an async [`JwksClient`] on its own runtime, running on a
+//! background thread, much like `reqwest::blocking`. Any source can be used, e.g. the
+//! [`WebSource`] re-exported here, and every builder option keeps working, background refreshes
+//! included.
+//!
+//! This is not a synchronous implementation of the client: the sources stay async and a hidden
+//! tokio runtime, with one worker thread, is started for each client. A blocking `WebSource`
+//! fetching the keys with `reqwest::blocking` is not implemented: the re-exported [`WebSource`] is
+//! the async one.
+//!
+//! The blocking client must not be used, nor dropped, from within an async runtime: it panics in
+//! that case.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tokio::runtime::Runtime;
+
+use crate::builder::JwksClientBuilder;
+use crate::error::Error;
+use crate::keyset::JsonWebKey;
+use crate::source::JwksSource;
+use crate::token::VerifiedToken;
+use crate::validation::ValidationPolicy;
+use crate::{JwksClient, JwksClientError};
+
+#[cfg(feature = "http")]
+pub use crate::source::{WebSource, WebSourceBuilder};
+
+// How long dropping the last clone of the client waits for in-flight fetches to complete
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Blocking version of [`JwksClient`], built by calling `build_blocking` on
+/// [`BlockingJwksClient::builder`].
+pub struct BlockingJwksClient<T: JwksSource> {
+    client: JwksClient<T>,
+    runtime: Arc<BlockingRuntime>,
+}
+
+impl<T: JwksSource> Clone for BlockingJwksClient<T> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+impl<T: JwksSource + Send + Sync + 'static> BlockingJwksClient<T> {
+    pub fn builder() -> JwksClientBuilder<T> {
+        JwksClientBuilder::new()
+    }
+
+    pub(crate) fn build(builder: JwksClientBuilder<T>, source: T) -> Result<Self, JwksClientError> {
+        let runtime: Runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("jwks-client-blocking")
+            .enable_all()
+            .build()
+            .map_err(Error::Runtime)?;
+
+        // Background tasks of the client are spawned on the runtime when building it
+        let client: JwksClient<T> = {
+            let _guard = runtime.enter();
+            builder.build(source)
+        };
+
+        Ok(Self {
+            client,
+            runtime: Arc::new(BlockingRuntime(Some(runtime))),
+        })
+    }
+
+    /// See [`JwksClient::get`].
+    pub fn get(&self, key_id: &str) -> Result<JsonWebKey, JwksClientError> {
+        self.runtime.block_on(self.client.get(key_id))
+    }
+
+    /// See [`JwksClient::get_opt`].
+    pub fn get_opt(&self, key_id: &str) -> Result<Option<JsonWebKey>, JwksClientError> {
+        self.runtime.block_on(self.client.get_opt(key_id))
+    }
+
+    /// See [`JwksClient::decode`].
+    pub fn decode<O: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: &[impl ToString],
+    ) -> Result<O, JwksClientError> {
+        self.runtime.block_on(self.client.decode(token, audience))
+    }
+
+    /// See [`JwksClient::decode_with`].
+    pub fn decode_with<O: DeserializeOwned>(
+        &self,
+        token: &str,
+        policy: &ValidationPolicy,
+    ) -> Result<O, JwksClientError> {
+        self.runtime
+            .block_on(self.client.decode_with(token, policy))
+    }
+
+    /// See [`JwksClient::decode_verbose`].
+    pub fn decode_verbose<O: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: &[impl ToString],
+    ) -> Result<VerifiedToken<O>, JwksClientError> {
+        self.runtime
+            .block_on(self.client.decode_verbose(token, audience))
+    }
+
+    /// See [`JwksClient::decode_verbose_with`].
+    pub fn decode_verbose_with<O: DeserializeOwned>(
+        &self,
+        token: &str,
+        policy: &ValidationPolicy,
+    ) -> Result<VerifiedToken<O>, JwksClientError> {
+        self.runtime
+            .block_on(self.client.decode_verbose_with(token, policy))
+    }
+}
+
+/// Runtime shut down without waiting indefinitely for the tasks still running on it.
+struct BlockingRuntime(Option<Runtime>);
+
+impl BlockingRuntime {
+    fn block_on<F: std::future::Future>(&self, future: F) -> F::Output {
+        self.0
+            .as_ref()
+            .expect("the runtime is only taken when dropped")
+            .block_on(future)
+    }
+}
+
+impl Drop for BlockingRuntime {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.0.take() {
+            runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "web-source")]
+    use std::time::Duration;
+
+    #[cfg(feature = "web-source")]
+    use httpmock::prelude::*;
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use serde_json::{json, Value};
+    #[cfg(feature = "web-source")]
+    use url::Url;
+
+    use crate::blocking::BlockingJwksClient;
+    #[cfg(feature = "web-source")]
+    use crate::blocking::WebSource;
+    use crate::client::test::{static_source_with_matching_key, TEST_RSA_PRIVATE_KEY};
+    #[cfg(feature = "web-source")]
+    use crate::client::test::{TEST_EXPONENT, TEST_MODULUS};
+    use crate::source::StaticSource;
+
+    fn token(kid: &str) -> String {
+        let encoding_key =
+            EncodingKey::from_rsa_pem(TEST_RSA_PRIVATE_KEY.trim().as_bytes()).unwrap();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+
+        jsonwebtoken::encode(&header, &json!({ "exp": 10000000000u64 }), &encoding_key).unwrap()
+    }
+
+    #[test]
+    fn decode_without_runtime() {
+        let client: BlockingJwksClient<StaticSource> = BlockingJwksClient::builder()
+            .build_blocking(static_source_with_matching_key("test-kid"))
+            .unwrap();
+
+        let claims: Value = client.decode(&token("test-kid"), &[] as &[String]).unwrap();
+        assert_eq!(json!({ "exp": 10000000000u64 }), claims);
+
+        assert!(client.get_opt("unknown-kid").unwrap().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot drop a runtime")]
+    fn drop_inside_async_context_panics() {
+        let client: BlockingJwksClient<StaticSource> = BlockingJwksClient::builder()
+            .build_blocking(static_source_with_matching_key("test-kid"))
+            .unwrap();
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async move { drop(client) });
+    }
+
+    #[cfg(feature = "web-source")]
+    #[test]
+    fn decode_with_web_source() {
+        let server = MockServer::start();
+        let path: &str = "/keys";
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path(path);
+
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(json!({
+                    "keys": [{
+                        "kty": "RSA",
+                        "alg": "RS256",
+                        "kid": "test-kid",
+                        "n": TEST_MODULUS,
+                        "e": TEST_EXPONENT
+                    }]
+                }));
+        });
+
+        let url: Url = Url::parse(&server.url(path)).unwrap();
+        let source: WebSource = WebSource::builder()
+            .with_timeout(Duration::from_secs(5))
+            .build(url)
+            .unwrap();
+        let client: BlockingJwksClient<WebSource> = BlockingJwksClient::builder()
+            .build_blocking(source)
+            .unwrap();
+
+        let claims: Result<Value, _> = client.decode(&token("test-kid"), &[] as &[String]);
+        assert!(claims.is_ok());
+        assert!(client.get("test-kid").is_ok());
+
+        mock.assert();
+    }
+}
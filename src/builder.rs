@@ -1,11 +1,38 @@
 use std::marker::PhantomData;
+use std::path::PathBuf;
 use std::time::Duration;
 
+use jsonwebtoken::{Algorithm, AlgorithmFamily};
+
+#[cfg(feature = "blocking")]
+use crate::blocking::BlockingJwksClient;
+use crate::cache::Cache;
+use crate::client::DEFAULT_CACHE_TTL;
+use crate::persistence::Snapshot;
 use crate::source::JwksSource;
+use crate::validation::ValidationPolicy;
 use crate::JwksClient;
+#[cfg(feature = "blocking")]
+use crate::JwksClientError;
+
+const DEFAULT_BACKGROUND_REFRESH_JITTER: f64 = 0.1;
+// Lowest time to live taken from cache headers, so that `Cache-Control: no-cache` doesn't cause a
+// fetch per lookup
+const MIN_CACHE_HEADERS_TTL: Duration = Duration::from_secs(1);
 
 pub struct JwksClientBuilder<T> {
     ttl_opt: Option<Duration>,
+    ttl_bounds_opt: Option<(Duration, Duration)>,
+    min_refresh_interval_opt: Option<Duration>,
+    negative_cache_ttl_opt: Option<Duration>,
+    max_staleness_opt: Option<Duration>,
+    snapshot_opt: Option<(PathBuf, Duration)>,
+    background_refresh_opt: Option<f64>,
+    background_refresh_jitter_opt: Option<f64>,
+    validation_policy_opt: Option<ValidationPolicy>,
+    allowed_algorithms_opt: Option<Vec<Algorithm>>,
+    kid_less_max_attempts_opt: Option<usize>,
+    offloaded_verification_opt: Option<usize>,
     t: PhantomData<*const T>,
     // New PR to add this?
     // cache_size: Option<usize>,
@@ -15,19 +42,260 @@ impl<T: JwksSource + Send + Sync + 'static> JwksClientBuilder<T> {
     pub(crate) fn new() -> Self {
         Self {
             ttl_opt: None,
+            ttl_bounds_opt: None,
+            min_refresh_interval_opt: None,
+            negative_cache_ttl_opt: None,
+            max_staleness_opt: None,
+            snapshot_opt: None,
+            background_refresh_opt: None,
+            background_refresh_jitter_opt: None,
+            validation_policy_opt: None,
+            allowed_algorithms_opt: None,
+            kid_less_max_attempts_opt: None,
+            offloaded_verification_opt: None,
             t: PhantomData,
         }
     }
 
-    pub fn time_to_live(&self, ttl: Duration) -> Self {
+    pub fn time_to_live(self, ttl: Duration) -> Self {
         Self {
             ttl_opt: Some(ttl),
-            t: PhantomData,
+            ..self
+        }
+    }
+
+    /// Uses the freshness reported by the source (e.g. the `Cache-Control: max-age` or `Expires`
+    /// headers of the JWKS endpoint) as time to live, clamped between `min_ttl` and `max_ttl`.
+    /// Key sets fetched without freshness information keep using [`time_to_live`](Self::time_to_live).
+    ///
+    /// `min_ttl` is raised to one second, unless `max_ttl` is shorter, so that responses forbidding
+    /// caching (`Cache-Control: no-cache` or `no-store`) don't trigger a fetch on every lookup.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min_ttl` is greater than `max_ttl`.
+    pub fn respect_cache_headers(self, min_ttl: Duration, max_ttl: Duration) -> Self {
+        assert!(
+            min_ttl <= max_ttl,
+            "`min_ttl` must not be greater than `max_ttl`"
+        );
+
+        let min_ttl: Duration = min_ttl.max(MIN_CACHE_HEADERS_TTL).min(max_ttl);
+
+        Self {
+            ttl_bounds_opt: Some((min_ttl, max_ttl)),
+            ..self
+        }
+    }
+
+    /// Sets the minimum interval between two refreshes of the key set triggered by a key id not
+    /// found in the cache. Lookups for unknown key ids within this interval fail without
+    /// contacting the source. By default there is no limit.
+    pub fn min_refresh_interval(self, interval: Duration) -> Self {
+        Self {
+            min_refresh_interval_opt: Some(interval),
+            ..self
+        }
+    }
+
+    /// Remembers key ids that are still missing after a refresh for the given time, so repeated
+    /// lookups for them fail without refreshing the key set. Disabled by default.
+    pub fn negative_cache_ttl(self, ttl: Duration) -> Self {
+        Self {
+            negative_cache_ttl_opt: Some(ttl),
+            ..self
+        }
+    }
+
+    /// Keeps serving an expired key while the key set cannot be refreshed, for at most
+    /// `max_staleness` after its expiration. Past that, the refresh error is returned.
+    /// By default expired keys are served for as long as refreshes fail.
+    pub fn stale_if_error(self, max_staleness: Duration) -> Self {
+        Self {
+            max_staleness_opt: Some(max_staleness),
+            ..self
+        }
+    }
+
+    /// Saves the key set to the given file after every refresh, replacing it atomically.
+    /// When building the client, the saved key set is loaded if it was fetched less than
+    /// `max_age` ago, so tokens can be verified even if the source is unreachable at startup.
+    ///
+    /// Requires a tokio runtime, since the file is written on the blocking thread pool.
+    pub fn snapshot_file(self, path: impl Into<PathBuf>, max_age: Duration) -> Self {
+        Self {
+            snapshot_opt: Some((path.into(), max_age)),
+            ..self
+        }
+    }
+
+    /// Enables a background task refreshing the key set every `ttl_fraction` of the time to live
+    /// (e.g. `0.8` refreshes after 80% of it), so that requests don't wait for a fetch once the
+    /// cache is warm. Refreshes are at least one second apart, even with a zero time to live.
+    /// A failed refresh is retried after a short backoff, up to thirty seconds, rather than at
+    /// the next interval. The task stops when the last clone of the client is dropped.
+    ///
+    /// When enabled, [`build`](Self::build) must be called from within a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl_fraction` is not in the `(0, 1]` range.
+    pub fn background_refresh(self, ttl_fraction: f64) -> Self {
+        assert!(
+            ttl_fraction > 0.0 && ttl_fraction <= 1.0,
+            "`ttl_fraction` must be in the (0, 1] range"
+        );
+
+        Self {
+            background_refresh_opt: Some(ttl_fraction),
+            ..self
+        }
+    }
+
+    /// Sets how much each background refresh is randomly moved, as a fraction of the refresh
+    /// interval. Defaults to `0.1`, i.e. refreshes happen within ±10% of the interval.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `jitter` is not in the `[0, 1]` range.
+    pub fn background_refresh_jitter(self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "`jitter` must be in the [0, 1] range"
+        );
+
+        Self {
+            background_refresh_jitter_opt: Some(jitter),
+            ..self
+        }
+    }
+
+    /// Sets the policy used by `decode` to validate tokens. Defaults to [`ValidationPolicy::default`].
+    pub fn validation_policy(self, validation_policy: ValidationPolicy) -> Self {
+        Self {
+            validation_policy_opt: Some(validation_policy),
+            ..self
+        }
+    }
+
+    /// Restricts the algorithms tokens can be signed with. Tokens whose header `alg` is not in
+    /// the list, or doesn't fit the type and curve of the key, are rejected before verifying their
+    /// signature. Defaults to every supported asymmetric algorithm.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the list contains an HMAC algorithm: a JWKS only publishes public keys, and
+    /// accepting HMAC would let anyone holding a public key sign tokens with it.
+    pub fn allowed_algorithms(self, algorithms: &[Algorithm]) -> Self {
+        assert!(
+            algorithms
+                .iter()
+                .all(|algorithm| algorithm.family() != AlgorithmFamily::Hmac),
+            "HMAC algorithms cannot be allowed"
+        );
+
+        Self {
+            allowed_algorithms_opt: Some(algorithms.to_vec()),
+            ..self
+        }
+    }
+
+    /// Verifies tokens without a `kid` header by trying the keys of the set whose type, algorithm
+    /// and use fit the token header, at most `max_attempts` of them, until one verifies the
+    /// signature. When the header has an `x5t` or `x5t#S256` thumbprint, only the keys with that
+    /// thumbprint are tried. Disabled by default: such tokens are rejected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is 0.
+    pub fn verify_without_kid(self, max_attempts: usize) -> Self {
+        assert!(max_attempts > 0, "`max_attempts` must be greater than 0");
+
+        Self {
+            kid_less_max_attempts_opt: Some(max_attempts),
+            ..self
+        }
+    }
+
+    /// Verifies token signatures on the tokio blocking thread pool instead of the calling task,
+    /// with at most `max_concurrent` verifications running at the same time. This keeps expensive
+    /// verifications (e.g. RSA-4096) from stalling the runtime under load, at the cost of a thread
+    /// handoff per token. Disabled by default.
+    ///
+    /// When enabled, tokens must be decoded from within a tokio runtime.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrent` is 0.
+    pub fn offload_verification(self, max_concurrent: usize) -> Self {
+        assert!(
+            max_concurrent > 0,
+            "`max_concurrent` must be greater than 0"
+        );
+
+        Self {
+            offloaded_verification_opt: Some(max_concurrent),
+            ..self
         }
     }
 
     #[must_use]
     pub fn build(self, source: T) -> JwksClient<T> {
-        JwksClient::new(source, self.ttl_opt)
+        let mut cache: Cache = Cache::new(self.ttl_opt.unwrap_or(DEFAULT_CACHE_TTL));
+
+        if let Some((min_ttl, max_ttl)) = self.ttl_bounds_opt {
+            cache = cache.with_time_to_live_bounds(min_ttl, max_ttl);
+        }
+
+        if let Some(interval) = self.min_refresh_interval_opt {
+            cache = cache.with_min_refresh_interval(interval);
+        }
+
+        if let Some(ttl) = self.negative_cache_ttl_opt {
+            cache = cache.with_negative_cache(ttl);
+        }
+
+        if let Some(max_staleness) = self.max_staleness_opt {
+            cache = cache.with_max_staleness(max_staleness);
+        }
+
+        if let Some((path, max_age)) = self.snapshot_opt {
+            cache = cache.with_snapshot(Snapshot::new(path, max_age));
+        }
+
+        let mut client: JwksClient<T> = JwksClient::with_cache(source, cache);
+
+        if let Some(allowed_algorithms) = self.allowed_algorithms_opt {
+            client = client.with_allowed_algorithms(&allowed_algorithms);
+        }
+
+        if let Some(validation_policy) = self.validation_policy_opt {
+            client = client.with_validation_policy(validation_policy);
+        }
+
+        if let Some(max_attempts) = self.kid_less_max_attempts_opt {
+            client = client.with_kid_less_verification(max_attempts);
+        }
+
+        if let Some(max_concurrent) = self.offloaded_verification_opt {
+            client = client.with_offloaded_verification(max_concurrent);
+        }
+
+        if let Some(ttl_fraction) = self.background_refresh_opt {
+            client = client.with_background_refresh(
+                ttl_fraction,
+                self.background_refresh_jitter_opt
+                    .unwrap_or(DEFAULT_BACKGROUND_REFRESH_JITTER),
+            );
+        }
+
+        client.with_change_refresh()
+    }
+
+    /// Builds a [`BlockingJwksClient`], running the client on its own runtime. Fails if the
+    /// runtime cannot be started.
+    #[cfg(feature = "blocking")]
+    pub fn build_blocking(self, source: T) -> Result<BlockingJwksClient<T>, JwksClientError> {
+        BlockingJwksClient::build(self, source)
     }
 }
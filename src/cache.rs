@@ -1,96 +1,518 @@
+use std::collections::HashMap;
 use std::future::Future;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::Duration as StdDuration;
 
 use chrono::{Duration, Utc};
+use jsonwebtoken::DecodingKey;
 use tokio::sync::RwLock;
 use tokio::sync::{RwLockReadGuard, RwLockWriteGuard};
 
+use crate::error::Error;
 use crate::keyset::JsonWebKeySet;
+use crate::persistence::Snapshot;
+use crate::source::{FetchedKeySet, Validators};
 use crate::{JsonWebKey, JwksClientError};
 
+// Upper bound on the number of unknown key ids remembered at the same time, so that a flood of
+// random `kid` values cannot grow the negative cache without limits.
+const NEGATIVE_CACHE_MAX_ENTRIES: usize = 1024;
+
 #[derive(Clone)]
 pub struct Cache {
     inner: Arc<RwLock<Entry>>,
+    // Held while refreshing the key set on a lookup, so that concurrent lookups share a refresh
+    refresh_lock: Arc<tokio::sync::Mutex<()>>,
     time_to_live: Duration,
     refreshed: Arc<AtomicBool>,
+    time_to_live_bounds: Option<(Duration, Duration)>,
+    min_refresh_interval: Option<Duration>,
+    last_miss_refresh_millis: Arc<AtomicI64>,
+    negative_cache: Option<NegativeCache>,
+    max_staleness: Option<Duration>,
+    snapshot: Option<Snapshot>,
 }
 
 impl Cache {
     pub fn new(time_to_live: StdDuration) -> Self {
-        let ttl: Duration = Duration::from_std(time_to_live)
-            .expect("Failed to convert from `std::time::Duration` to `chrono::Duration`");
+        let ttl: Duration = to_chrono_duration(time_to_live);
         let json_web_key_set: JsonWebKeySet = JsonWebKeySet::empty();
 
         Self {
             inner: Arc::new(RwLock::new(Entry::new(json_web_key_set, &ttl))),
+            refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
             time_to_live: ttl,
             refreshed: Arc::new(AtomicBool::new(false)),
+            time_to_live_bounds: None,
+            min_refresh_interval: None,
+            last_miss_refresh_millis: Arc::new(AtomicI64::new(i64::MIN)),
+            negative_cache: None,
+            max_staleness: None,
+            snapshot: None,
+        }
+    }
+
+    /// Uses the freshness reported by the source as time to live, clamped between `min` and `max`.
+    /// Key sets without freshness information still use the configured time to live.
+    pub fn with_time_to_live_bounds(self, min: StdDuration, max: StdDuration) -> Self {
+        Self {
+            time_to_live_bounds: Some((to_chrono_duration(min), to_chrono_duration(max))),
+            ..self
+        }
+    }
+
+    /// Minimum time between two refreshes triggered by a key id missing from the cache.
+    pub fn with_min_refresh_interval(self, min_refresh_interval: StdDuration) -> Self {
+        Self {
+            min_refresh_interval: Some(to_chrono_duration(min_refresh_interval)),
+            ..self
         }
     }
 
-    pub async fn get_or_refresh<F>(
+    /// Remembers key ids not found after a refresh for the given time, answering them without
+    /// refreshing the key set again.
+    pub fn with_negative_cache(self, time_to_live: StdDuration) -> Self {
+        Self {
+            negative_cache: Some(NegativeCache::new(to_chrono_duration(time_to_live))),
+            ..self
+        }
+    }
+
+    /// Bounds how long after its expiration a cached key keeps being served when the key set
+    /// cannot be refreshed. Without a bound, stale keys are served as long as refreshes fail.
+    pub fn with_max_staleness(self, max_staleness: StdDuration) -> Self {
+        Self {
+            max_staleness: Some(to_chrono_duration(max_staleness)),
+            ..self
+        }
+    }
+
+    /// Saves every refreshed key set to the snapshot, and starts from the saved one if it is
+    /// recent enough. The restored keys expire one time to live after they were fetched.
+    /// Must be called before the cache is cloned.
+    pub fn with_snapshot(self, snapshot: Snapshot) -> Self {
+        let inner: Arc<RwLock<Entry>> = match snapshot.load() {
+            Some((set, fetched_at_millis)) => {
+                let mut entry: Entry = Entry::new(set, &self.time_to_live);
+                entry.expire_time_millis = fetched_at_millis + self.time_to_live.num_milliseconds();
+                Arc::new(RwLock::new(entry))
+            }
+            None => self.inner,
+        };
+
+        Self {
+            inner,
+            snapshot: Some(snapshot),
+            ..self
+        }
+    }
+
+    pub fn time_to_live(&self) -> StdDuration {
+        self.time_to_live.to_std().unwrap_or_default()
+    }
+
+    /// Replaces the cached key set, regardless of the current entry being expired or not.
+    /// Returns the time to live given to the new entry.
+    pub async fn store(&self, fetched: FetchedKeySet) -> StdDuration {
+        let ttl: Duration = self.time_to_live_for(&fetched);
+        let mut guard: RwLockWriteGuard<Entry> = self.inner.write().await;
+        guard.update(fetched, &ttl);
+        self.updated(&guard);
+        ttl.to_std().unwrap_or_default()
+    }
+
+    /// Returns the keys with the given id, in the order of the set, refreshing the set when it is
+    /// expired or the key is missing. Several keys share the id when a merged source keeps them
+    /// all. `fetch` is given the validators of the cached set, if any.
+    pub async fn get_or_refresh<R, F>(
         &self,
         key: &str,
-        future: F,
-    ) -> Result<JsonWebKey, JwksClientError>
+        fetch: R,
+    ) -> Result<Vec<CachedKey>, JwksClientError>
     where
-        F: Future<Output = Result<JsonWebKeySet, JwksClientError>> + Send + 'static,
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
+    {
+        self.lookup_or_refresh(Some(key), |entry: &Entry| entry.get(key), fetch)
+            .await
+    }
+
+    /// Returns the keys of the set matching the predicate, refreshing the set like
+    /// [`get_or_refresh`](Self::get_or_refresh) when it is expired or no key matches.
+    pub async fn find_or_refresh<P, R, F>(
+        &self,
+        predicate: P,
+        fetch: R,
+    ) -> Result<Vec<CachedKey>, JwksClientError>
+    where
+        P: Fn(&JsonWebKey) -> bool,
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
+    {
+        self.lookup_or_refresh(None, |entry: &Entry| entry.find(&predicate), fetch)
+            .await
+    }
+
+    /// Refreshes the set after the keys matching the predicate failed to verify a token, in case
+    /// they have been rotated, and returns the matching keys of the new set. Throttled like the
+    /// refreshes on a missing key: returns `None` when the refresh is skipped.
+    pub async fn refresh_and_find<P, R, F>(
+        &self,
+        predicate: P,
+        fetch: R,
+    ) -> Result<Option<Vec<CachedKey>>, JwksClientError>
+    where
+        P: Fn(&JsonWebKey) -> bool,
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
+    {
+        self.refreshed.store(false, Ordering::SeqCst);
+        let _refresh_guard: tokio::sync::MutexGuard<()> = self.refresh_lock.lock().await;
+
+        // Another task could have refreshed the set while we were waiting for the lock
+        if !self.refreshed.load(Ordering::SeqCst) {
+            if self.is_miss_refresh_throttled() {
+                tracing::debug!("no key verified the token, refresh skipped by rate limit");
+                return Ok(None);
+            }
+
+            self.fetch_and_store(fetch).await?;
+            self.last_miss_refresh_millis
+                .store(Utc::now().timestamp_millis(), Ordering::SeqCst);
+        }
+
+        self.inner.read().await.find(&predicate).map(Some)
+    }
+
+    /// Validators of the cached key set, to make its next refresh conditional.
+    pub async fn validators(&self) -> Option<Validators> {
+        self.inner.read().await.validators.clone()
+    }
+
+    /// Looks up the entry, refreshing it when expired or when the lookup fails. `key` is the key
+    /// id looked up, if any, used for the negative cache.
+    async fn lookup_or_refresh<T, L, R, F>(
+        &self,
+        key: Option<&str>,
+        lookup: L,
+        fetch: R,
+    ) -> Result<T, JwksClientError>
+    where
+        L: Fn(&Entry) -> Result<T, JwksClientError>,
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
     {
         let read: RwLockReadGuard<Entry> = self.inner.read().await;
         let is_entry_expired: bool = read.is_expired();
-        let get_key_result: Result<JsonWebKey, JwksClientError> = read.set.get_key(key).cloned();
+        let expire_time_millis: i64 = read.expire_time_millis;
+        let lookup_result: Result<T, JwksClientError> = lookup(&read);
         // Drop RwLock read guard prematurely to be able to write in the lock
         drop(read);
 
-        match get_key_result {
+        match lookup_result {
             // Key not found. Maybe a refresh is needed
-            Err(_) => self.try_refresh(future).await.and_then(|v| v.take_key(key)),
+            Err(error) => self.refresh_on_miss(key, error, lookup, fetch).await,
             // Specified key exist but a refresh is needed
-            Ok(json_web_key) if is_entry_expired => self
-                .try_refresh(future)
-                .await
-                .and_then(|v| v.take_key(key))
-                .or(Ok(json_web_key)),
+            // Only a failed fetch falls back to the stale key, not a refreshed set without it
+            Ok(found) if is_entry_expired => match self.try_refresh(fetch).await {
+                Ok(()) => lookup(&*self.inner.read().await),
+                Err(error) => self.stale_if_error(key, found, expire_time_millis, error),
+            },
             // Specified key exist and is still valid. Return this one
-            Ok(key) => Ok(key),
+            Ok(found) => Ok(found),
+        }
+    }
+
+    async fn refresh_on_miss<T, L, R, F>(
+        &self,
+        key: Option<&str>,
+        miss: JwksClientError,
+        lookup: L,
+        fetch: R,
+    ) -> Result<T, JwksClientError>
+    where
+        L: Fn(&Entry) -> Result<T, JwksClientError>,
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
+    {
+        if let (Some(negative_cache), Some(key)) = (&self.negative_cache, key) {
+            if negative_cache.contains(key) {
+                return Err(miss);
+            }
+        }
+
+        let _refresh_guard: tokio::sync::MutexGuard<()> = self.refresh_lock.lock().await;
+
+        // Another task could have refreshed the set while we were waiting for the lock
+        if let Ok(found) = lookup(&*self.inner.read().await) {
+            return Ok(found);
+        }
+
+        if self.is_miss_refresh_throttled() {
+            tracing::debug!(kid = key, "key not found, refresh skipped by rate limit");
+            return Err(miss);
         }
+
+        // Only successful refreshes are throttled, so that a failing source keeps reporting its
+        // error rather than a missing key
+        self.fetch_and_store(fetch).await?;
+        self.last_miss_refresh_millis
+            .store(Utc::now().timestamp_millis(), Ordering::SeqCst);
+
+        let result: Result<T, JwksClientError> = lookup(&*self.inner.read().await);
+
+        if result.is_err() {
+            if let (Some(negative_cache), Some(key)) = (&self.negative_cache, key) {
+                negative_cache.insert(key);
+            }
+        }
+
+        result
     }
 
-    async fn try_refresh<F>(&self, future: F) -> Result<JsonWebKeySet, JwksClientError>
+    async fn try_refresh<R, F>(&self, fetch: R) -> Result<(), JwksClientError>
     where
-        F: Future<Output = Result<JsonWebKeySet, JwksClientError>> + Send + 'static,
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
     {
         self.refreshed.store(false, Ordering::SeqCst);
-        let mut guard: RwLockWriteGuard<Entry> = self.inner.write().await;
+        let _refresh_guard: tokio::sync::MutexGuard<()> = self.refresh_lock.lock().await;
 
         if !self.refreshed.load(Ordering::SeqCst) {
-            let set: JsonWebKeySet = future.await?;
-            *guard = Entry::new(set.clone(), &self.time_to_live);
-            self.refreshed.store(true, Ordering::SeqCst);
-            Ok(set)
-        } else {
-            Ok(guard.set.clone())
+            self.fetch_and_store(fetch).await?;
+        }
+
+        Ok(())
+        // we drop the refresh guard here so "refresh=true" for the other threads/tasks
+    }
+
+    /// Fetches the key set, conditionally to the cached one, and stores it. Callers hold the
+    /// refresh lock, so that a single refresh runs at a time, but not the entry lock: lookups of
+    /// the cached keys don't wait for the fetch and its retries.
+    async fn fetch_and_store<R, F>(&self, fetch: R) -> Result<(), JwksClientError>
+    where
+        R: FnOnce(Option<Validators>) -> F,
+        F: Future<Output = Result<FetchedKeySet, JwksClientError>> + Send + 'static,
+    {
+        let validators: Option<Validators> = self.validators().await;
+        let fetched: FetchedKeySet = fetch(validators).await?;
+        self.store(fetched).await;
+        Ok(())
+    }
+
+    /// Falls back to the expired keys when the refresh failed, unless they expired too long ago.
+    fn stale_if_error<T>(
+        &self,
+        key: Option<&str>,
+        found: T,
+        expire_time_millis: i64,
+        error: JwksClientError,
+    ) -> Result<T, JwksClientError> {
+        let staleness_millis: i64 = Utc::now()
+            .timestamp_millis()
+            .saturating_sub(expire_time_millis);
+
+        match self.max_staleness {
+            Some(max_staleness) if staleness_millis > max_staleness.num_milliseconds() => {
+                tracing::error!(kid = key, staleness_millis, %error, "refresh failed, cached key is too stale");
+                Err(error)
+            }
+            _ => {
+                tracing::warn!(kid = key, staleness_millis, %error, "refresh failed, serving stale key");
+                Ok(found)
+            }
+        }
+    }
+
+    fn time_to_live_for(&self, fetched: &FetchedKeySet) -> Duration {
+        match (self.time_to_live_bounds, fetched.max_age()) {
+            (Some((min, max)), Some(max_age)) => {
+                Duration::from_std(max_age).unwrap_or(max).clamp(min, max)
+            }
+            _ => self.time_to_live,
+        }
+    }
+
+    fn is_miss_refresh_throttled(&self) -> bool {
+        match self.min_refresh_interval {
+            Some(interval) => {
+                let last_refresh: i64 = self.last_miss_refresh_millis.load(Ordering::SeqCst);
+                Utc::now().timestamp_millis().saturating_sub(last_refresh)
+                    < interval.num_milliseconds()
+            }
+            None => false,
+        }
+    }
+
+    /// Bookkeeping after the entry has been refreshed with a fetched key set.
+    fn updated(&self, entry: &Entry) {
+        self.refreshed.store(true, Ordering::SeqCst);
+        self.clear_negative_cache();
+
+        if let Some(snapshot) = &self.snapshot {
+            snapshot.save(entry.set.clone());
+        }
+    }
+
+    fn clear_negative_cache(&self) {
+        if let Some(negative_cache) = &self.negative_cache {
+            negative_cache.clear();
+        }
+    }
+}
+
+/// A key from the cached set, along with its `DecodingKey` if it could be prepared.
+#[derive(Clone)]
+pub struct CachedKey {
+    pub key: JsonWebKey,
+    pub decoding_key: Option<Arc<DecodingKey>>,
+}
+
+impl From<(&JsonWebKey, &Option<Arc<DecodingKey>>)> for CachedKey {
+    fn from((key, decoding_key): (&JsonWebKey, &Option<Arc<DecodingKey>>)) -> Self {
+        Self {
+            key: key.clone(),
+            decoding_key: decoding_key.clone(),
         }
-        // we drop the write guard here so "refresh=true" for the other threads/tasks
     }
 }
 
 struct Entry {
     set: JsonWebKeySet,
+    // Decoding key of each key of the set, in the same order, built once when the set is stored.
+    // Not keyed by key id, since several keys can share one.
+    decoding_keys: Vec<Option<Arc<DecodingKey>>>,
+    // Validators returned with the set, passed back to the source that returned it
+    validators: Option<Validators>,
     expire_time_millis: i64,
 }
 
 impl Entry {
     fn new(set: JsonWebKeySet, expiration: &Duration) -> Self {
+        let decoding_keys: Vec<Option<Arc<DecodingKey>>> = set
+            .iter()
+            .map(|key: &JsonWebKey| match key.to_decoding_key() {
+                Ok(decoding_key) => Some(Arc::new(decoding_key)),
+                Err(error) => {
+                    tracing::warn!(kid = key.key_id(), %error, "cannot prepare decoding key");
+                    None
+                }
+            })
+            .collect();
+
         Self {
             set,
+            decoding_keys,
+            validators: None,
             expire_time_millis: Utc::now().timestamp_millis() + expiration.num_milliseconds(),
         }
     }
 
+    /// Replaces the key set with the fetched one, or only extends the expiration if the source
+    /// reported it as not modified. A not modified answer checked against validators other than
+    /// the ones of the current set (e.g. replaced by a concurrent refresh) is ignored.
+    fn update(&mut self, fetched: FetchedKeySet, expiration: &Duration) {
+        match fetched.into_parts() {
+            (Some(set), validators) => {
+                *self = Self::new(set, expiration);
+                self.validators = validators;
+            }
+            (None, Some(validators)) if self.validators.as_ref() != Some(&validators) => {
+                tracing::debug!("not modified answer for another key set, ignored");
+            }
+            (None, _) => {
+                self.expire_time_millis =
+                    Utc::now().timestamp_millis() + expiration.num_milliseconds()
+            }
+        }
+    }
+
+    fn get(&self, key_id: &str) -> Result<Vec<CachedKey>, JwksClientError> {
+        let found: Vec<CachedKey> = self
+            .keys()
+            .filter(|(key, _)| key.has_key_id(key_id))
+            .map(CachedKey::from)
+            .collect();
+
+        if found.is_empty() {
+            Err(Error::KeyNotFound(key_id.to_string()).into())
+        } else {
+            Ok(found)
+        }
+    }
+
+    fn find(
+        &self,
+        predicate: impl Fn(&JsonWebKey) -> bool,
+    ) -> Result<Vec<CachedKey>, JwksClientError> {
+        let found: Vec<CachedKey> = self
+            .keys()
+            .filter(|(key, _)| predicate(key))
+            .map(CachedKey::from)
+            .collect();
+
+        if found.is_empty() {
+            Err(Error::NoMatchingKey.into())
+        } else {
+            Ok(found)
+        }
+    }
+
+    /// The keys of the set, each with its own decoding key.
+    fn keys(&self) -> impl Iterator<Item = (&JsonWebKey, &Option<Arc<DecodingKey>>)> {
+        self.set.iter().zip(&self.decoding_keys)
+    }
+
     fn is_expired(&self) -> bool {
         Utc::now().timestamp_millis() > self.expire_time_millis
     }
 }
+
+#[derive(Clone)]
+struct NegativeCache {
+    // Key id -> expire time in millis
+    entries: Arc<Mutex<HashMap<String, i64>>>,
+    time_to_live: Duration,
+}
+
+impl NegativeCache {
+    fn new(time_to_live: Duration) -> Self {
+        Self {
+            entries: Arc::new(Mutex::new(HashMap::new())),
+            time_to_live,
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .is_some_and(|expire_time_millis| Utc::now().timestamp_millis() <= *expire_time_millis)
+    }
+
+    fn insert(&self, key: &str) {
+        let now: i64 = Utc::now().timestamp_millis();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+
+        if entries.len() >= NEGATIVE_CACHE_MAX_ENTRIES {
+            entries.retain(|_, expire_time_millis| now <= *expire_time_millis);
+        }
+
+        if entries.len() < NEGATIVE_CACHE_MAX_ENTRIES {
+            entries.insert(key.to_string(), now + self.time_to_live.num_milliseconds());
+        }
+    }
+
+    fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+fn to_chrono_duration(duration: StdDuration) -> Duration {
+    Duration::from_std(duration)
+        .expect("Failed to convert from `std::time::Duration` to `chrono::Duration`")
+}
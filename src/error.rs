@@ -1,19 +1,84 @@
+use std::io;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use jsonwebtoken::errors::ErrorKind;
+use jsonwebtoken::errors::ErrorKind as JwtErrorKind;
+use jsonwebtoken::Algorithm;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
+    #[cfg(feature = "web-source")]
     #[error("Failed fetching the key: {0}")]
     Reqwest(#[from] reqwest::Error),
+    #[error("Failed fetching the key: {0}")]
+    Fetch(Box<dyn std::error::Error + Send + Sync>),
+    #[cfg(feature = "http")]
+    #[error("Failed fetching the key, unexpected response status: {0}")]
+    HttpStatus(http::StatusCode),
+    #[error("Invalid response body: {0}")]
+    InvalidResponseBody(serde_json::Error),
     #[error("Cannot find key for key_id: {0}")]
     KeyNotFound(String),
     #[error("Token decoding error: {0}")]
     JsonWebToken(#[from] jsonwebtoken::errors::Error),
     #[error("Missing Kid value in the JWT token header")]
     MissingKid,
+    #[error("No key of the set fits the JWT token header")]
+    NoMatchingKey,
     #[error("The operation is not supported for this key type: {0}")]
     InvalidOperation(String),
+    #[error("The token algorithm is not allowed: {0:?}")]
+    AlgorithmNotAllowed(Algorithm),
+    #[error("The token algorithm {algorithm:?} doesn't fit the key {key_id}")]
+    AlgorithmMismatch {
+        algorithm: Algorithm,
+        key_id: String,
+    },
+    #[error("Invalid issuer url: {0}")]
+    InvalidIssuer(String),
+    #[error("Discovered issuer {found} doesn't match the expected issuer {expected}")]
+    IssuerMismatch { expected: String, found: String },
+    #[error("The source is unavailable, the circuit is open")]
+    CircuitOpen,
+    #[error("Cannot read the key set file {}: {source}", path.display())]
+    ReadFile { path: PathBuf, source: io::Error },
+    #[error("Invalid key set in file {}: {source}", path.display())]
+    InvalidKeySetFile {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Every source failed: {}", join_errors(.0))]
+    AllSourcesFailed(Vec<JwksClientError>),
+    #[error("Some sources failed: {}", join_named_errors(.0))]
+    SourcesFailed(Vec<(String, JwksClientError)>),
+    #[error("The key id {0} is provided by more than one source")]
+    DuplicateKeyId(String),
+    #[error("Missing iss claim in the JWT token")]
+    MissingIssuer,
+    #[error("The token issuer is not accepted: {0}")]
+    UnknownIssuer(String),
+    #[error("The token verification was cancelled, the runtime is shutting down")]
+    VerificationCancelled,
+    #[cfg(feature = "blocking")]
+    #[error("Cannot start the runtime of the blocking client: {0}")]
+    Runtime(io::Error),
+}
+
+fn join_errors(errors: &[JwksClientError]) -> String {
+    errors
+        .iter()
+        .enumerate()
+        .map(|(index, error)| format!("[{index}] {error}"))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn join_named_errors(errors: &[(String, JwksClientError)]) -> String {
+    errors
+        .iter()
+        .map(|(name, error)| format!("[{name}] {error}"))
+        .collect::<Vec<String>>()
+        .join(", ")
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -34,21 +99,86 @@ impl From<jsonwebtoken::errors::Error> for JwksClientError {
     }
 }
 
+/// The category of a [`JwksClientError`], to react to failures without matching on messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The keys (or the discovery document) couldn't be fetched from the source.
+    Fetch,
+    /// No key in the key set matches the `kid` of the token.
+    KeyNotFound,
+    /// The token is malformed, its signature is wrong or it doesn't satisfy the validation policy.
+    InvalidToken,
+    /// The token `exp` claim is in the past.
+    ExpiredToken,
+    /// The key cannot be used to verify the token.
+    UnsupportedKey,
+    /// The client or the source has been configured with invalid values.
+    Configuration,
+    /// The source failed too many times in a row and is not called until its cooldown elapses.
+    CircuitOpen,
+    /// The operation was cancelled before completing, e.g. because the runtime is shutting down.
+    Cancelled,
+}
+
 impl JwksClientError {
-    pub fn is_jwt_expired(&self) -> bool {
+    /// Wraps the failure of a custom [`JwksSource`](crate::source::JwksSource) to fetch the keys,
+    /// whatever its transport. Its kind is [`ErrorKind::Fetch`].
+    pub fn fetch(error: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Error::Fetch(error.into()).into()
+    }
+
+    pub fn kind(&self) -> ErrorKind {
         match self {
-            JwksClientError::Error(e) => e.is_jwt_expired(),
+            JwksClientError::Error(e) => e.kind(),
         }
     }
+
+    pub fn is_jwt_expired(&self) -> bool {
+        self.kind() == ErrorKind::ExpiredToken
+    }
+
+    pub fn is_key_not_found(&self) -> bool {
+        self.kind() == ErrorKind::KeyNotFound
+    }
 }
 
 impl Error {
-    fn is_jwt_expired(&self) -> bool {
+    fn kind(&self) -> ErrorKind {
         match self {
-            Error::JsonWebToken(err) => {
-                matches!(err.kind(), ErrorKind::ExpiredSignature)
-            }
-            _ => false,
+            #[cfg(feature = "web-source")]
+            Error::Reqwest(_) => ErrorKind::Fetch,
+            #[cfg(feature = "http")]
+            Error::HttpStatus(_) => ErrorKind::Fetch,
+            Error::Fetch(_)
+            | Error::InvalidResponseBody(_)
+            | Error::IssuerMismatch { .. }
+            | Error::ReadFile { .. }
+            | Error::InvalidKeySetFile { .. }
+            | Error::AllSourcesFailed(_)
+            | Error::SourcesFailed(_)
+            | Error::DuplicateKeyId(_) => ErrorKind::Fetch,
+            Error::KeyNotFound(_) | Error::NoMatchingKey => ErrorKind::KeyNotFound,
+            Error::JsonWebToken(err) => match err.kind() {
+                JwtErrorKind::ExpiredSignature => ErrorKind::ExpiredToken,
+                JwtErrorKind::InvalidEcdsaKey
+                | JwtErrorKind::InvalidEddsaKey
+                | JwtErrorKind::InvalidRsaKey(_)
+                | JwtErrorKind::InvalidKeyFormat
+                | JwtErrorKind::UnsupportedAlgorithm => ErrorKind::UnsupportedKey,
+                _ => ErrorKind::InvalidToken,
+            },
+            Error::MissingKid
+            | Error::AlgorithmNotAllowed(_)
+            | Error::AlgorithmMismatch { .. }
+            | Error::MissingIssuer
+            | Error::UnknownIssuer(_) => ErrorKind::InvalidToken,
+            Error::InvalidOperation(_) => ErrorKind::UnsupportedKey,
+            Error::InvalidIssuer(_) => ErrorKind::Configuration,
+            Error::VerificationCancelled => ErrorKind::Cancelled,
+            #[cfg(feature = "blocking")]
+            Error::Runtime(_) => ErrorKind::Configuration,
+            Error::CircuitOpen => ErrorKind::CircuitOpen,
         }
     }
 }
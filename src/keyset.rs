@@ -1,57 +1,148 @@
 // https://tools.ietf.org/id/draft-ietf-jose-json-web-key-00.html#rfc.section.3.1
 
-use serde::Deserialize;
+use std::str::FromStr;
+
+use jsonwebtoken::{Algorithm, AlgorithmFamily, DecodingKey};
+use serde::{Deserialize, Serialize};
 
 use crate::{error::Error, JwksClientError};
 
-#[derive(Debug, Deserialize, Clone)]
+/// Keys that cannot be parsed or are not supported (e.g. symmetric `oct` keys) are skipped instead
+/// of failing the whole set. See [`JsonWebKeySet::skipped_keys`]. Keys without `kid` are kept.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(from = "RawJsonWebKeySet")]
 pub struct JsonWebKeySet {
     keys: Vec<JsonWebKey>,
+    #[serde(skip_serializing)]
+    skipped_keys: Vec<SkippedKey>,
 }
 
 impl From<Vec<JsonWebKey>> for JsonWebKeySet {
     fn from(keys: Vec<JsonWebKey>) -> Self {
-        Self { keys }
+        Self {
+            keys,
+            skipped_keys: vec![],
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawJsonWebKeySet {
+    keys: Vec<serde_json::Value>,
+}
+
+impl From<RawJsonWebKeySet> for JsonWebKeySet {
+    fn from(raw: RawJsonWebKeySet) -> Self {
+        let mut keys: Vec<JsonWebKey> = Vec::with_capacity(raw.keys.len());
+        let mut skipped_keys: Vec<SkippedKey> = vec![];
+
+        for value in raw.keys {
+            let key_id: Option<String> = string_field(&value, "kid");
+            let key_type: Option<String> = string_field(&value, "kty");
+
+            match serde_json::from_value::<JsonWebKey>(value) {
+                Ok(key) => keys.push(key),
+                Err(error) => {
+                    tracing::warn!(kid = ?key_id, kty = ?key_type, %error, "skipping key");
+                    skipped_keys.push(SkippedKey {
+                        key_id,
+                        key_type,
+                        reason: error.to_string(),
+                    });
+                }
+            }
+        }
+
+        Self { keys, skipped_keys }
+    }
+}
+
+fn string_field(value: &serde_json::Value, field: &str) -> Option<String> {
+    value.get(field)?.as_str().map(str::to_string)
+}
+
+/// A key of the set that has been ignored because it couldn't be used.
+#[derive(Debug, Clone)]
+pub struct SkippedKey {
+    key_id: Option<String>,
+    key_type: Option<String>,
+    reason: String,
+}
+
+impl SkippedKey {
+    pub fn key_id(&self) -> Option<&str> {
+        self.key_id.as_deref()
+    }
+
+    pub fn key_type(&self) -> Option<&str> {
+        self.key_type.as_deref()
+    }
+
+    /// Why the key has been skipped.
+    pub fn reason(&self) -> &str {
+        &self.reason
     }
 }
 
 impl JsonWebKeySet {
+    pub fn new(keys: Vec<JsonWebKey>) -> Self {
+        Self::from(keys)
+    }
+
     pub(crate) fn empty() -> Self {
-        Self { keys: vec![] }
+        Self::from(vec![])
     }
 
     pub fn get_key(&self, key_id: &str) -> Result<&JsonWebKey, JwksClientError> {
         self.keys
             .iter()
-            .find(|key| key.key_id() == key_id)
+            .find(|key| key.has_key_id(key_id))
             .ok_or_else(|| Error::KeyNotFound(key_id.to_string()).into())
     }
 
     pub fn take_key(self, key_id: &str) -> Result<JsonWebKey, JwksClientError> {
         self.keys
             .into_iter()
-            .find(|key| key.key_id() == key_id)
+            .find(|key| key.has_key_id(key_id))
             .ok_or_else(|| Error::KeyNotFound(key_id.to_string()).into())
     }
 
     pub fn keys(self) -> Vec<JsonWebKey> {
         self.keys
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &JsonWebKey> {
+        self.keys.iter()
+    }
+
+    /// Keys found in the fetched set that have been left out.
+    pub fn skipped_keys(&self) -> &[SkippedKey] {
+        &self.skipped_keys
+    }
+
+    pub(crate) fn with_skipped_keys(self, skipped_keys: Vec<SkippedKey>) -> Self {
+        Self {
+            skipped_keys,
+            ..self
+        }
+    }
 }
 
 // https://tools.ietf.org/id/draft-ietf-jose-json-web-key-00.html#rfc.section.3
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "kty")]
 pub enum JsonWebKey {
-    #[serde(alias = "RSA")]
+    #[serde(rename = "RSA", alias = "Rsa")]
     Rsa(RsaPublicJwk),
-    #[serde(alias = "EC")]
+    #[serde(rename = "EC", alias = "Ec")]
     Ec(EcPublicJwk),
-    #[serde(alias = "OKP")]
+    #[serde(rename = "OKP", alias = "Okp")]
     Okp(OkpPublicJwk),
 }
 
 impl JsonWebKey {
+    /// The `kid` of the key, empty for keys published without one. Such keys can only verify
+    /// tokens without `kid`, see `JwksClientBuilder::verify_without_kid`.
     pub fn key_id(&self) -> &str {
         match self {
             JsonWebKey::Rsa(rsa_pk) => rsa_pk.key_id(),
@@ -60,6 +151,17 @@ impl JsonWebKey {
         }
     }
 
+    /// Whether the key has the given `kid`. Keys without `kid` have none.
+    pub(crate) fn has_key_id(&self, key_id: &str) -> bool {
+        let own_key_id: Option<&str> = match self {
+            JsonWebKey::Rsa(rsa_pk) => rsa_pk.key_id.as_deref(),
+            JsonWebKey::Ec(ec_pk) => ec_pk.key_id.as_deref(),
+            JsonWebKey::Okp(okp_pk) => okp_pk.key_id.as_deref(),
+        };
+
+        own_key_id == Some(key_id)
+    }
+
     pub fn alg(&self) -> Option<&str> {
         match self {
             JsonWebKey::Rsa(rsa_pk) => rsa_pk.algorithm(),
@@ -68,6 +170,34 @@ impl JsonWebKey {
         }
     }
 
+    /// Builds the key used by `jsonwebtoken` to verify signatures.
+    pub fn to_decoding_key(&self) -> Result<DecodingKey, jsonwebtoken::errors::Error> {
+        match self {
+            JsonWebKey::Rsa(jwk) => DecodingKey::from_rsa_components(jwk.modulus(), jwk.exponent()),
+            JsonWebKey::Ec(jwk) => DecodingKey::from_ec_components(jwk.x(), jwk.y()),
+            JsonWebKey::Okp(jwk) => DecodingKey::from_ed_components(jwk.x()),
+        }
+    }
+
+    /// Whether a token signed with `algorithm` can be verified with this key: the algorithm must
+    /// belong to the key type (and curve), and be the one declared by the key, if any.
+    pub fn supports_algorithm(&self, algorithm: Algorithm) -> bool {
+        if let Some(alg) = self.alg() {
+            if Algorithm::from_str(alg).ok() != Some(algorithm) {
+                return false;
+            }
+        }
+
+        match self {
+            JsonWebKey::Rsa(_) => algorithm.family() == AlgorithmFamily::Rsa,
+            JsonWebKey::Ec(ec_pk) => matches!(
+                (ec_pk.curve(), algorithm),
+                ("P-256", Algorithm::ES256) | ("P-384", Algorithm::ES384)
+            ),
+            JsonWebKey::Okp(okp_pk) => okp_pk.curve() == "Ed25519" && algorithm == Algorithm::EdDSA,
+        }
+    }
+
     pub fn as_rsa_public_key(&self) -> Result<&RsaPublicJwk, Error> {
         match self {
             JsonWebKey::Rsa(rsa_pk) => Ok(rsa_pk),
@@ -89,63 +219,140 @@ impl JsonWebKey {
         }
     }
 
-    #[cfg(test)]
-    pub fn x5t(&self) -> Option<String> {
+    /// The intended use of the key, if declared.
+    pub fn r#use(&self) -> Option<Use> {
+        match self {
+            JsonWebKey::Rsa(rsa_pk) => rsa_pk.r#use(),
+            JsonWebKey::Ec(ec_pk) => ec_pk.r#use(),
+            JsonWebKey::Okp(okp_pk) => okp_pk.r#use(),
+        }
+    }
+
+    /// The `x5t` (SHA-1) thumbprint of the key certificate. Only RSA keys declare it.
+    pub fn x5t(&self) -> Option<&str> {
+        match self {
+            JsonWebKey::Rsa(rsa_pk) => rsa_pk.thumbprint(),
+            JsonWebKey::Ec(_ec_pk) => None,
+            JsonWebKey::Okp(_okp_pk) => None,
+        }
+    }
+
+    /// The `x5t#S256` (SHA-256) thumbprint of the key certificate. Only RSA keys declare it.
+    pub fn x5t_s256(&self) -> Option<&str> {
         match self {
-            JsonWebKey::Rsa(rsa_pk) => rsa_pk.x5t.clone(),
+            JsonWebKey::Rsa(rsa_pk) => rsa_pk.thumbprint_sha256(),
             JsonWebKey::Ec(_ec_pk) => None,
             JsonWebKey::Okp(_okp_pk) => None,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl From<RsaPublicJwk> for JsonWebKey {
+    fn from(jwk: RsaPublicJwk) -> Self {
+        JsonWebKey::Rsa(jwk)
+    }
+}
+
+impl From<EcPublicJwk> for JsonWebKey {
+    fn from(jwk: EcPublicJwk) -> Self {
+        JsonWebKey::Ec(jwk)
+    }
+}
+
+impl From<OkpPublicJwk> for JsonWebKey {
+    fn from(jwk: OkpPublicJwk) -> Self {
+        JsonWebKey::Okp(jwk)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RsaPublicJwk {
+    #[serde(skip_serializing_if = "Option::is_none")]
     r#use: Option<Use>,
-    #[serde(rename(deserialize = "alg"))]
+    #[serde(rename = "alg", skip_serializing_if = "Option::is_none")]
     algorithm: Option<String>,
-    #[serde(rename(deserialize = "kid"))]
-    key_id: String,
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
     // X.509 certificate chain
-    #[serde(rename(deserialize = "x5c"))]
+    #[serde(rename = "x5c", skip_serializing_if = "Option::is_none")]
     certificates: Option<Vec<String>>,
-    #[cfg(test)]
-    x5t: Option<String>,
-    #[serde(rename(deserialize = "n"))]
+    // Base64url encoded SHA-1 and SHA-256 thumbprints of the X.509 certificate
+    #[serde(rename = "x5t", skip_serializing_if = "Option::is_none")]
+    thumbprint: Option<String>,
+    #[serde(rename = "x5t#S256", skip_serializing_if = "Option::is_none")]
+    thumbprint_sha256: Option<String>,
+    #[serde(rename = "n")]
     modulus: String,
-    #[serde(rename(deserialize = "e"))]
+    #[serde(rename = "e")]
     exponent: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct EcPublicJwk {
-    #[serde(rename(deserialize = "alg"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r#use: Option<Use>,
+    #[serde(rename = "alg", skip_serializing_if = "Option::is_none")]
     algorithm: Option<String>,
-    #[serde(rename(deserialize = "kid"))]
-    key_id: String,
-    #[serde(rename(deserialize = "crv"))]
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    #[serde(rename = "crv")]
     curve: String,
-    #[serde(rename(deserialize = "x"))]
+    #[serde(rename = "x")]
     x: String,
-    #[serde(rename(deserialize = "y"))]
+    #[serde(rename = "y")]
     y: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct OkpPublicJwk {
-    #[serde(rename(deserialize = "alg"))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    r#use: Option<Use>,
+    #[serde(rename = "alg", skip_serializing_if = "Option::is_none")]
     algorithm: Option<String>,
-    #[serde(rename(deserialize = "kid"))]
-    key_id: String,
-    #[serde(rename(deserialize = "crv"))]
+    #[serde(rename = "kid", default, skip_serializing_if = "Option::is_none")]
+    key_id: Option<String>,
+    #[serde(rename = "crv")]
     curve: String,
-    #[serde(rename(deserialize = "x"))]
+    #[serde(rename = "x")]
     x: String,
 }
 
 impl RsaPublicJwk {
+    /// Builds the key from its base64url encoded modulus and exponent.
+    pub fn new(
+        key_id: impl Into<String>,
+        modulus: impl Into<String>,
+        exponent: impl Into<String>,
+    ) -> Self {
+        Self {
+            r#use: None,
+            algorithm: None,
+            key_id: Some(key_id.into()),
+            certificates: None,
+            thumbprint: None,
+            thumbprint_sha256: None,
+            modulus: modulus.into(),
+            exponent: exponent.into(),
+        }
+    }
+
+    pub fn with_algorithm(self, algorithm: Algorithm) -> Self {
+        Self {
+            algorithm: Some(algorithm_name(algorithm)),
+            ..self
+        }
+    }
+
+    pub fn with_use(self, r#use: Use) -> Self {
+        Self {
+            r#use: Some(r#use),
+            ..self
+        }
+    }
+
+    /// See [`JsonWebKey::key_id`].
     pub fn key_id(&self) -> &str {
-        &self.key_id
+        self.key_id.as_deref().unwrap_or_default()
     }
 
     pub fn modulus(&self) -> &str {
@@ -167,11 +374,55 @@ impl RsaPublicJwk {
     pub fn certificates(&self) -> Option<&[String]> {
         self.certificates.as_deref()
     }
+
+    pub fn thumbprint(&self) -> Option<&str> {
+        self.thumbprint.as_deref()
+    }
+
+    pub fn thumbprint_sha256(&self) -> Option<&str> {
+        self.thumbprint_sha256.as_deref()
+    }
 }
 
 impl EcPublicJwk {
+    /// Builds the key from its curve (e.g. `P-256`) and base64url encoded coordinates.
+    pub fn new(
+        key_id: impl Into<String>,
+        curve: impl Into<String>,
+        x: impl Into<String>,
+        y: impl Into<String>,
+    ) -> Self {
+        Self {
+            r#use: None,
+            algorithm: None,
+            key_id: Some(key_id.into()),
+            curve: curve.into(),
+            x: x.into(),
+            y: y.into(),
+        }
+    }
+
+    pub fn with_algorithm(self, algorithm: Algorithm) -> Self {
+        Self {
+            algorithm: Some(algorithm_name(algorithm)),
+            ..self
+        }
+    }
+
+    pub fn with_use(self, r#use: Use) -> Self {
+        Self {
+            r#use: Some(r#use),
+            ..self
+        }
+    }
+
+    /// See [`JsonWebKey::key_id`].
     pub fn key_id(&self) -> &str {
-        &self.key_id
+        self.key_id.as_deref().unwrap_or_default()
+    }
+
+    pub fn r#use(&self) -> Option<Use> {
+        self.r#use
     }
 
     pub fn algorithm(&self) -> Option<&str> {
@@ -192,8 +443,38 @@ impl EcPublicJwk {
 }
 
 impl OkpPublicJwk {
+    /// Builds the key from its curve (e.g. `Ed25519`) and base64url encoded public key.
+    pub fn new(key_id: impl Into<String>, curve: impl Into<String>, x: impl Into<String>) -> Self {
+        Self {
+            r#use: None,
+            algorithm: None,
+            key_id: Some(key_id.into()),
+            curve: curve.into(),
+            x: x.into(),
+        }
+    }
+
+    pub fn with_algorithm(self, algorithm: Algorithm) -> Self {
+        Self {
+            algorithm: Some(algorithm_name(algorithm)),
+            ..self
+        }
+    }
+
+    pub fn with_use(self, r#use: Use) -> Self {
+        Self {
+            r#use: Some(r#use),
+            ..self
+        }
+    }
+
+    /// See [`JsonWebKey::key_id`].
     pub fn key_id(&self) -> &str {
-        &self.key_id
+        self.key_id.as_deref().unwrap_or_default()
+    }
+
+    pub fn r#use(&self) -> Option<Use> {
+        self.r#use
     }
 
     pub fn algorithm(&self) -> Option<&str> {
@@ -209,8 +490,16 @@ impl OkpPublicJwk {
     }
 }
 
-#[derive(Debug, Deserialize, Clone, Copy)]
-#[serde(rename_all(deserialize = "lowercase"))]
+// The `alg` value of the algorithm, as registered in https://www.rfc-editor.org/rfc/rfc7518
+fn algorithm_name(algorithm: Algorithm) -> String {
+    match serde_json::to_value(algorithm) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => unreachable!("algorithms are serialized as their `alg` name"),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum Use {
     Sig,
     Enc,
@@ -218,7 +507,13 @@ pub enum Use {
 
 #[cfg(test)]
 mod tests {
-    use super::JsonWebKeySet;
+    use std::str::FromStr;
+
+    use jsonwebtoken::Algorithm;
+
+    use super::{
+        algorithm_name, EcPublicJwk, JsonWebKey, JsonWebKeySet, OkpPublicJwk, RsaPublicJwk, Use,
+    };
 
     #[test]
     fn deserialize_public_rsa_key_set() -> Result<(), Box<dyn std::error::Error>> {
@@ -259,6 +554,7 @@ mod tests {
             {
               "alg": "ES256",
               "kty": "EC",
+              "use": "enc",
               "crv": "P-256",
               "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
               "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
@@ -272,6 +568,7 @@ mod tests {
         let key = keyset.get_key("test-key")?;
 
         assert_eq!("ES256", key.alg().unwrap());
+        assert_eq!(Some(Use::Enc), key.r#use());
 
         let ec_pk = key.as_ec_public_key()?;
         assert_eq!("LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc", ec_pk.x());
@@ -288,6 +585,7 @@ mod tests {
             {
               "alg": "EdDSA",
               "kty": "OKP",
+              "use": "sig",
               "crv": "Ed25519",
               "x": "11qYAYtk8C4QW2oZ3hJhiuK6V3rC1z1z5t3YhZ1t1nM",
               "kid": "okp-key"
@@ -300,6 +598,7 @@ mod tests {
         let key = keyset.get_key("okp-key")?;
 
         assert_eq!("EdDSA", key.alg().unwrap());
+        assert_eq!(Some(Use::Sig), key.r#use());
 
         let okp_pk = key.as_okp_public_key()?;
         assert_eq!("Ed25519", okp_pk.curve());
@@ -307,4 +606,172 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn deserialize_key_set_skipping_unusable_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let keys = r#"
+        {
+          "keys": [
+            {
+              "kty": "oct",
+              "kid": "symmetric-key",
+              "k": "GawgguFyGrWKav7AX4VKUg"
+            },
+            {
+              "alg": "ES256",
+              "kty": "EC",
+              "crv": "P-256",
+              "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+              "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0"
+            },
+            {
+              "alg": "ES256",
+              "kty": "EC",
+              "crv": "P-256",
+              "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+              "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+              "kid": "test-key"
+            },
+            "not a key"
+          ]
+        }
+        "#;
+
+        let keyset: JsonWebKeySet = serde_json::from_str(keys)?;
+        assert!(keyset.get_key("test-key").is_ok());
+        assert!(keyset.get_key("symmetric-key").is_err());
+
+        // The key without kid is kept, but cannot be looked up by kid
+        assert_eq!(2, keyset.iter().count());
+        assert_eq!("", keyset.iter().next().unwrap().key_id());
+        assert!(keyset.get_key("").is_err());
+
+        let skipped_keys = keyset.skipped_keys();
+        assert_eq!(2, skipped_keys.len());
+
+        assert_eq!(Some("symmetric-key"), skipped_keys[0].key_id());
+        assert_eq!(Some("oct"), skipped_keys[0].key_type());
+        assert!(skipped_keys[0].reason().contains("unknown variant `oct`"));
+
+        assert_eq!(None, skipped_keys[1].key_id());
+
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_key_set() -> Result<(), Box<dyn std::error::Error>> {
+        let keys = serde_json::json!({
+            "keys": [
+                {
+                    "alg": "ES256",
+                    "kty": "EC",
+                    "crv": "P-256",
+                    "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+                    "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+                    "kid": "ec-key"
+                },
+                {
+                    "kty": "OKP",
+                    "crv": "Ed25519",
+                    "x": "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+                    "kid": "okp-key"
+                },
+                {
+                    "kty": "oct",
+                    "kid": "symmetric-key",
+                    "k": "GawgguFyGrWKav7AX4VKUg"
+                }
+            ]
+        });
+
+        let keyset: JsonWebKeySet = serde_json::from_value(keys.clone())?;
+        let serialized: serde_json::Value = serde_json::to_value(&keyset)?;
+
+        // Skipped keys are left out
+        let mut expected = keys;
+        expected["keys"].as_array_mut().unwrap().pop();
+        assert_eq!(expected, serialized);
+
+        let keyset: JsonWebKeySet = serde_json::from_value(serialized)?;
+        assert!(keyset.get_key("ec-key").is_ok());
+        assert!(keyset.get_key("okp-key").is_ok());
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_key_set() {
+        let keyset: JsonWebKeySet = JsonWebKeySet::new(vec![
+            RsaPublicJwk::new("rsa-key", "qjNzuylUQpyU9qX3", "AQAB")
+                .with_algorithm(Algorithm::RS256)
+                .with_use(Use::Sig)
+                .into(),
+            EcPublicJwk::new(
+                "ec-key",
+                "P-256",
+                "LEBfQpwTDXJtLFiPcnYvGv",
+                "a1Or3ovkpH12b0o3ruZU",
+            )
+            .into(),
+            OkpPublicJwk::new("okp-key", "Ed25519", "11qYAYKxCrfVS_7TyWQHOg")
+                .with_algorithm(Algorithm::EdDSA)
+                .into(),
+        ]);
+
+        let rsa_key: &JsonWebKey = keyset.get_key("rsa-key").unwrap();
+        assert_eq!(Some("RS256"), rsa_key.alg());
+        assert!(rsa_key.supports_algorithm(Algorithm::RS256));
+
+        assert!(keyset
+            .get_key("ec-key")
+            .unwrap()
+            .supports_algorithm(Algorithm::ES256));
+        assert_eq!(Some("EdDSA"), keyset.get_key("okp-key").unwrap().alg());
+    }
+
+    #[test]
+    fn algorithm_name_is_alg_value() {
+        for algorithm in [Algorithm::PS512, Algorithm::ES384, Algorithm::EdDSA] {
+            let name: String = algorithm_name(algorithm);
+            assert_eq!(algorithm, Algorithm::from_str(&name).unwrap());
+        }
+    }
+
+    #[test]
+    fn key_supports_algorithm() -> Result<(), Box<dyn std::error::Error>> {
+        let keys = r#"
+        {
+          "keys": [
+            {
+              "kty": "EC",
+              "crv": "P-256",
+              "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+              "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+              "kid": "ec-key"
+            },
+            {
+              "alg": "EdDSA",
+              "kty": "OKP",
+              "crv": "Ed25519",
+              "x": "11qYAYtk8C4QW2oZ3hJhiuK6V3rC1z1z5t3YhZ1t1nM",
+              "kid": "okp-key"
+            }
+          ]
+        }
+        "#;
+
+        let keyset: JsonWebKeySet = serde_json::from_str(keys)?;
+
+        let ec_key = keyset.get_key("ec-key")?;
+        assert!(ec_key.supports_algorithm(Algorithm::ES256));
+        assert!(!ec_key.supports_algorithm(Algorithm::ES384));
+        assert!(!ec_key.supports_algorithm(Algorithm::RS256));
+        assert!(!ec_key.supports_algorithm(Algorithm::HS256));
+
+        let okp_key = keyset.get_key("okp-key")?;
+        assert!(okp_key.supports_algorithm(Algorithm::EdDSA));
+        assert!(!okp_key.supports_algorithm(Algorithm::ES256));
+
+        Ok(())
+    }
 }
@@ -1,10 +1,22 @@
 pub use client::JwksClient;
-pub use error::JwksClientError;
-pub use keyset::{JsonWebKey, JsonWebKeySet};
+pub use error::{ErrorKind, JwksClientError};
+pub use keyset::{
+    EcPublicJwk, JsonWebKey, JsonWebKeySet, OkpPublicJwk, RsaPublicJwk, SkippedKey, Use,
+};
+pub use multi_issuer::{MultiIssuerJwksClient, MultiIssuerJwksClientBuilder};
+pub use token::VerifiedToken;
+pub use validation::ValidationPolicy;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod builder;
 mod cache;
 mod client;
 mod error;
 mod keyset;
+mod multi_issuer;
+mod persistence;
+mod refresh;
 pub mod source;
+mod token;
+mod validation;
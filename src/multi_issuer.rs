@@ -0,0 +1,476 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::error::Error;
+use crate::source::{BoxedJwksSource, JwksSource};
+use crate::token::VerifiedToken;
+use crate::validation::ValidationPolicy;
+use crate::{JwksClient, JwksClientError};
+
+// Upper bound on the clients created for issuers matching a pattern, so that tokens with random
+// issuers cannot make the client grow without limits.
+const DEFAULT_MAX_PATTERN_ISSUERS: usize = 64;
+
+type SourceFactory = dyn Fn(&str) -> Result<BoxedJwksSource, JwksClientError> + Send + Sync;
+
+/// Client verifying tokens of several issuers, each one with its own source, cache and
+/// validation policy.
+///
+/// The token is routed by its `iss` claim, read before verifying the signature. Tokens of
+/// issuers that are neither configured nor matching an issuer pattern are rejected without
+/// fetching any key. The validation policy of every issuer always requires the `iss` claim to be
+/// that issuer.
+#[derive(Clone)]
+pub struct MultiIssuerJwksClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    clients: RwLock<HashMap<String, JwksClient<BoxedJwksSource>>>,
+    patterns: Vec<(IssuerPattern, Box<SourceFactory>)>,
+    validation_policy: ValidationPolicy,
+    max_pattern_issuers: usize,
+    // Number of clients kept for issuers matching a pattern, only updated holding the lock of
+    // `clients`
+    pattern_issuers: AtomicUsize,
+}
+
+#[derive(Deserialize)]
+struct IssuerClaim {
+    iss: Option<String>,
+}
+
+impl MultiIssuerJwksClient {
+    pub fn builder() -> MultiIssuerJwksClientBuilder {
+        MultiIssuerJwksClientBuilder::new()
+    }
+
+    /// Decodes and validates the token with the client of its issuer.
+    /// See [`JwksClient::decode`].
+    pub async fn decode<O: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: &[impl ToString],
+    ) -> Result<O, JwksClientError> {
+        let (client, new_issuer) = self.client_for(token)?;
+        let claims: O = client.decode(token, audience).await?;
+        self.keep_client(new_issuer, client);
+        Ok(claims)
+    }
+
+    /// Same as [`decode`](Self::decode), also returning the token header and the key that
+    /// verified it.
+    pub async fn decode_verbose<O: DeserializeOwned>(
+        &self,
+        token: &str,
+        audience: &[impl ToString],
+    ) -> Result<VerifiedToken<O>, JwksClientError> {
+        let (client, new_issuer) = self.client_for(token)?;
+        let verified: VerifiedToken<O> = client.decode_verbose(token, audience).await?;
+        self.keep_client(new_issuer, client);
+        Ok(verified)
+    }
+
+    /// Returns the client of the token issuer, creating it if the issuer matches a pattern. A
+    /// created client comes with its issuer and is only kept with [`keep_client`](Self::keep_client)
+    /// once it verified the token, so that unverified tokens can't use up the pattern issuers.
+    fn client_for(
+        &self,
+        token: &str,
+    ) -> Result<(JwksClient<BoxedJwksSource>, Option<String>), JwksClientError> {
+        // The signature can't be checked before knowing the issuer: the claim is only used to
+        // pick the client, which then validates it
+        let claims: IssuerClaim = jsonwebtoken::dangerous::insecure_decode_claims(token)?;
+        let issuer: String = claims.iss.ok_or(Error::MissingIssuer)?;
+
+        if let Some(client) = self
+            .inner
+            .clients
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&issuer)
+        {
+            return Ok((client.clone(), None));
+        }
+
+        let Some((_, factory)) = self
+            .inner
+            .patterns
+            .iter()
+            .find(|(pattern, _)| pattern.matches(&issuer))
+        else {
+            return Err(Error::UnknownIssuer(issuer).into());
+        };
+
+        if self.inner.pattern_issuers.load(Ordering::Relaxed) >= self.inner.max_pattern_issuers {
+            tracing::warn!(
+                issuer,
+                "too many issuers matching a pattern, issuer rejected"
+            );
+            return Err(Error::UnknownIssuer(issuer).into());
+        }
+
+        let client: JwksClient<BoxedJwksSource> =
+            issuer_client(&issuer, factory(&issuer)?, &self.inner.validation_policy);
+
+        Ok((client, Some(issuer)))
+    }
+
+    /// Keeps the client created for an issuer matching a pattern, after it verified a token.
+    fn keep_client(&self, new_issuer: Option<String>, client: JwksClient<BoxedJwksSource>) {
+        let Some(issuer) = new_issuer else {
+            return;
+        };
+
+        let mut clients = self
+            .inner
+            .clients
+            .write()
+            .unwrap_or_else(|e| e.into_inner());
+
+        // Another task could have kept a client for the issuer while this one was verifying
+        if clients.contains_key(&issuer)
+            || self.inner.pattern_issuers.load(Ordering::Relaxed) >= self.inner.max_pattern_issuers
+        {
+            return;
+        }
+
+        clients.insert(issuer, client);
+        self.inner.pattern_issuers.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+fn issuer_client(
+    issuer: &str,
+    source: BoxedJwksSource,
+    validation_policy: &ValidationPolicy,
+) -> JwksClient<BoxedJwksSource> {
+    JwksClient::builder()
+        .validation_policy(validation_policy.clone().with_issuers(&[issuer]))
+        .build(source)
+}
+
+enum IssuerEntry {
+    Source(BoxedJwksSource),
+    Client(Box<JwksClient<BoxedJwksSource>>),
+}
+
+pub struct MultiIssuerJwksClientBuilder {
+    issuers: Vec<(String, IssuerEntry)>,
+    patterns: Vec<(IssuerPattern, Box<SourceFactory>)>,
+    validation_policy_opt: Option<ValidationPolicy>,
+    max_pattern_issuers_opt: Option<usize>,
+}
+
+impl MultiIssuerJwksClientBuilder {
+    fn new() -> Self {
+        Self {
+            issuers: vec![],
+            patterns: vec![],
+            validation_policy_opt: None,
+            max_pattern_issuers_opt: None,
+        }
+    }
+
+    /// Accepts the tokens of the issuer, verified with the keys of the given source and the
+    /// validation policy of this builder.
+    pub fn with_issuer<T: JwksSource + Send + Sync + 'static>(
+        mut self,
+        issuer: impl Into<String>,
+        source: T,
+    ) -> Self {
+        self.issuers
+            .push((issuer.into(), IssuerEntry::Source(Box::new(source))));
+        self
+    }
+
+    /// Accepts the tokens of the issuer, verified by the given client (e.g. to configure its
+    /// cache). Its validation policy is kept, only requiring the `iss` claim to be the issuer.
+    pub fn with_issuer_client(
+        mut self,
+        issuer: impl Into<String>,
+        client: JwksClient<BoxedJwksSource>,
+    ) -> Self {
+        self.issuers
+            .push((issuer.into(), IssuerEntry::Client(Box::new(client))));
+        self
+    }
+
+    /// Accepts the tokens of any issuer matching the pattern, creating its source with the given
+    /// function the first time one of its tokens is decoded (e.g. with OIDC discovery). The source
+    /// is created again for each token until one of them is verified.
+    ///
+    /// In the pattern, `*` matches one or more letters, digits, `.`, `-` or `_`, so it can't span
+    /// more than a host name or a path segment, e.g. `https://*.eu.auth0.com/`.
+    pub fn with_issuer_pattern<F>(mut self, pattern: &str, source_factory: F) -> Self
+    where
+        F: Fn(&str) -> Result<BoxedJwksSource, JwksClientError> + Send + Sync + 'static,
+    {
+        self.patterns
+            .push((IssuerPattern::new(pattern), Box::new(source_factory)));
+        self
+    }
+
+    /// The validation policy used for the issuers configured without their own client.
+    pub fn validation_policy(self, validation_policy: ValidationPolicy) -> Self {
+        Self {
+            validation_policy_opt: Some(validation_policy),
+            ..self
+        }
+    }
+
+    /// Maximum number of issuers matching a pattern that are accepted, only counting the issuers
+    /// that had a token verified. Defaults to 64.
+    pub fn max_pattern_issuers(self, max_pattern_issuers: usize) -> Self {
+        Self {
+            max_pattern_issuers_opt: Some(max_pattern_issuers),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> MultiIssuerJwksClient {
+        let validation_policy: ValidationPolicy = self.validation_policy_opt.unwrap_or_default();
+
+        let clients: HashMap<String, JwksClient<BoxedJwksSource>> = self
+            .issuers
+            .into_iter()
+            .map(|(issuer, entry)| {
+                let client: JwksClient<BoxedJwksSource> = match entry {
+                    IssuerEntry::Source(source) => {
+                        issuer_client(&issuer, source, &validation_policy)
+                    }
+                    IssuerEntry::Client(client) => {
+                        let policy: ValidationPolicy =
+                            client.validation_policy().clone().with_issuers(&[&issuer]);
+                        (*client).with_validation_policy(policy)
+                    }
+                };
+                (issuer, client)
+            })
+            .collect();
+
+        MultiIssuerJwksClient {
+            inner: Arc::new(Inner {
+                clients: RwLock::new(clients),
+                patterns: self.patterns,
+                validation_policy,
+                max_pattern_issuers: self
+                    .max_pattern_issuers_opt
+                    .unwrap_or(DEFAULT_MAX_PATTERN_ISSUERS),
+                pattern_issuers: AtomicUsize::new(0),
+            }),
+        }
+    }
+}
+
+/// Issuer pattern where `*` stands for one or more characters allowed in a host name label or a
+/// path segment.
+struct IssuerPattern {
+    pattern: String,
+}
+
+impl IssuerPattern {
+    fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_string(),
+        }
+    }
+
+    fn matches(&self, issuer: &str) -> bool {
+        glob(self.pattern.as_bytes(), issuer.as_bytes())
+    }
+}
+
+fn glob(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (1..=text.len())
+            .take_while(|length: &usize| is_wildcard_char(text[length - 1]))
+            .any(|length: usize| glob(rest, &text[length..])),
+        Some((expected, rest)) => text.first() == Some(expected) && glob(rest, &text[1..]),
+    }
+}
+
+fn is_wildcard_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'.' | b'-' | b'_')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use jsonwebtoken::{Algorithm, EncodingKey, Header};
+    use serde_json::{json, Value};
+
+    use super::{IssuerPattern, MultiIssuerJwksClient};
+    use crate::client::test::{static_source_with_matching_key, TEST_RSA_PRIVATE_KEY};
+    use crate::error::{Error, ErrorKind};
+    use crate::source::{BoxedJwksSource, MockJwksSource};
+    use crate::JwksClientError;
+
+    fn token(kid: &str, issuer: Option<&str>) -> String {
+        let encoding_key =
+            EncodingKey::from_rsa_pem(TEST_RSA_PRIVATE_KEY.trim().as_bytes()).unwrap();
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+
+        let claims: Value = match issuer {
+            Some(issuer) => json!({ "exp": 10000000000u64, "iss": issuer }),
+            None => json!({ "exp": 10000000000u64 }),
+        };
+
+        jsonwebtoken::encode(&header, &claims, &encoding_key).unwrap()
+    }
+
+    fn unused_source() -> BoxedJwksSource {
+        let mut source = MockJwksSource::new();
+        source.expect_fetch_keys_with_freshness().never();
+        source.expect_changes().returning(|| None);
+        Box::new(source)
+    }
+
+    #[tokio::test]
+    async fn decode_routes_token_to_its_issuer() {
+        let client: MultiIssuerJwksClient = MultiIssuerJwksClient::builder()
+            .with_issuer(
+                "https://first.example.com/",
+                static_source_with_matching_key("first-kid"),
+            )
+            .with_issuer("https://second.example.com/", unused_source())
+            .build();
+
+        let claims: Value = client
+            .decode(
+                &token("first-kid", Some("https://first.example.com/")),
+                &[] as &[String],
+            )
+            .await
+            .unwrap();
+        assert_eq!("https://first.example.com/", claims["iss"]);
+    }
+
+    #[tokio::test]
+    async fn decode_rejects_unknown_issuer() {
+        let client: MultiIssuerJwksClient = MultiIssuerJwksClient::builder()
+            .with_issuer("https://first.example.com/", unused_source())
+            .build();
+
+        let error: JwksClientError = client
+            .decode::<Value>(
+                &token("kid", Some("https://evil.example.com/")),
+                &[] as &[String],
+            )
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(ErrorKind::InvalidToken, error.kind());
+
+        match error {
+            JwksClientError::Error(err) => match *err {
+                Error::UnknownIssuer(ref issuer) => assert_eq!("https://evil.example.com/", issuer),
+                _ => {
+                    eprintln!("{err}");
+                    unreachable!()
+                }
+            },
+        }
+
+        let error: JwksClientError = client
+            .decode::<Value>(&token("kid", None), &[] as &[String])
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(ErrorKind::InvalidToken, error.kind());
+    }
+
+    #[tokio::test]
+    async fn decode_creates_client_for_issuer_matching_pattern() {
+        let client: MultiIssuerJwksClient = MultiIssuerJwksClient::builder()
+            .with_issuer_pattern("https://*.example.com/", |_issuer: &str| {
+                Ok(Box::new(static_source_with_matching_key("kid")) as BoxedJwksSource)
+            })
+            .max_pattern_issuers(1)
+            .build();
+
+        let token_1: String = token("kid", Some("https://tenant-1.example.com/"));
+        assert!(client
+            .decode::<Value>(&token_1, &[] as &[String])
+            .await
+            .is_ok());
+        assert!(client
+            .decode::<Value>(&token_1, &[] as &[String])
+            .await
+            .is_ok());
+
+        // Over the limit of issuers created from patterns
+        let token_2: String = token("kid", Some("https://tenant-2.example.com/"));
+        let error: JwksClientError = client
+            .decode::<Value>(&token_2, &[] as &[String])
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(ErrorKind::InvalidToken, error.kind());
+    }
+
+    #[tokio::test]
+    async fn decode_only_keeps_client_for_issuer_matching_pattern_once_verified() {
+        let created: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
+        let counter: Arc<AtomicUsize> = created.clone();
+        let client: MultiIssuerJwksClient = MultiIssuerJwksClient::builder()
+            .with_issuer_pattern("https://*.example.com/", move |_issuer: &str| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(Box::new(static_source_with_matching_key("kid")) as BoxedJwksSource)
+            })
+            .max_pattern_issuers(2)
+            .build();
+
+        // Tokens of junk issuers fail to verify and don't count towards the limit
+        for tenant in 0..5 {
+            let junk: String = token(
+                "unknown-kid",
+                Some(&format!("https://junk-{tenant}.example.com/")),
+            );
+            assert!(client
+                .decode::<Value>(&junk, &[] as &[String])
+                .await
+                .is_err());
+        }
+        assert_eq!(5, created.load(Ordering::SeqCst));
+
+        let real: String = token("kid", Some("https://tenant.example.com/"));
+        for _ in 0..2 {
+            assert!(client
+                .decode::<Value>(&real, &[] as &[String])
+                .await
+                .is_ok());
+        }
+        assert_eq!(6, created.load(Ordering::SeqCst));
+
+        let other: String = token("kid", Some("https://other-tenant.example.com/"));
+        assert!(client
+            .decode_verbose::<Value>(&other, &[] as &[String])
+            .await
+            .is_ok());
+    }
+
+    #[test]
+    fn issuer_pattern_matches() {
+        let pattern: IssuerPattern = IssuerPattern::new("https://*.eu.auth0.com/");
+
+        assert!(pattern.matches("https://tenant.eu.auth0.com/"));
+        assert!(pattern.matches("https://my-tenant.eu.auth0.com/"));
+        assert!(!pattern.matches("https://.eu.auth0.com/"));
+        assert!(!pattern.matches("https://evil.com/.eu.auth0.com/"));
+        assert!(!pattern.matches("https://evil.com?.eu.auth0.com/"));
+        assert!(!pattern.matches("https://tenant.eu.auth0.com/other"));
+
+        let pattern: IssuerPattern = IssuerPattern::new("https://cognito-idp.*.amazonaws.com/*");
+        assert!(pattern.matches("https://cognito-idp.eu-west-1.amazonaws.com/eu-west-1_abc123"));
+        assert!(!pattern.matches("https://cognito-idp.eu-west-1.amazonaws.com/a/b"));
+    }
+}
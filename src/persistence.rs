@@ -0,0 +1,191 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::keyset::JsonWebKeySet;
+
+/// File keeping the last fetched key set, so that a client starting while the source is
+/// unreachable can still verify tokens.
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    path: PathBuf,
+    max_age: Duration,
+    // Fetch time of the last saved key set, so that an older one never overwrites it
+    last_saved_millis: Arc<Mutex<i64>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    fetched_at_millis: i64,
+    key_set: JsonWebKeySet,
+}
+
+impl Snapshot {
+    pub(crate) fn new(path: PathBuf, max_age: Duration) -> Self {
+        Self {
+            path,
+            max_age,
+            last_saved_millis: Arc::new(Mutex::new(i64::MIN)),
+        }
+    }
+
+    /// Reads the saved key set along with its fetch time, unless it is older than the maximum
+    /// age. A missing or unreadable file is not an error: the client just starts empty.
+    pub(crate) fn load(&self) -> Option<(JsonWebKeySet, i64)> {
+        let content: Vec<u8> = match fs::read(&self.path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return None,
+            Err(error) => {
+                tracing::warn!(path = %self.path.display(), %error, "cannot read key set snapshot");
+                return None;
+            }
+        };
+
+        let file: SnapshotFile = match serde_json::from_slice(&content) {
+            Ok(file) => file,
+            Err(error) => {
+                tracing::warn!(path = %self.path.display(), %error, "cannot parse key set snapshot");
+                return None;
+            }
+        };
+
+        let age_millis: i64 = Utc::now()
+            .timestamp_millis()
+            .saturating_sub(file.fetched_at_millis);
+
+        if age_millis > i64::try_from(self.max_age.as_millis()).unwrap_or(i64::MAX) {
+            tracing::info!(path = %self.path.display(), age_millis, "key set snapshot is too old");
+            return None;
+        }
+
+        tracing::debug!(path = %self.path.display(), age_millis, "key set snapshot loaded");
+        Some((file.key_set, file.fetched_at_millis))
+    }
+
+    /// Writes the key set in the background, replacing the previous snapshot atomically.
+    /// Must be called within a tokio runtime.
+    pub(crate) fn save(&self, key_set: JsonWebKeySet) {
+        let fetched_at_millis: i64 = Utc::now().timestamp_millis();
+        let snapshot: Snapshot = self.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut last_saved_millis = snapshot
+                .last_saved_millis
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+
+            if *last_saved_millis > fetched_at_millis {
+                return;
+            }
+
+            let file: SnapshotFile = SnapshotFile {
+                fetched_at_millis,
+                key_set,
+            };
+
+            match write_atomically(&snapshot.path, &file) {
+                Ok(()) => *last_saved_millis = fetched_at_millis,
+                Err(error) => {
+                    tracing::warn!(path = %snapshot.path.display(), %error, "cannot write key set snapshot")
+                }
+            }
+        });
+    }
+}
+
+/// Writes to a temporary file next to the target and renames it, so readers never see a
+/// partially written snapshot.
+fn write_atomically(path: &Path, file: &SnapshotFile) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp_path: PathBuf = path.with_file_name(tmp_name);
+
+    let mut tmp_file: File = File::create(&tmp_path)?;
+    serde_json::to_writer(&mut tmp_file, file)?;
+    tmp_file.flush()?;
+    tmp_file.sync_all()?;
+
+    fs::rename(&tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{write_atomically, Snapshot, SnapshotFile};
+    use crate::JsonWebKeySet;
+
+    fn key_set() -> JsonWebKeySet {
+        serde_json::from_value(json!({
+            "keys": [{
+                "alg": "ES256",
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+                "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+                "kid": "test-key"
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn save_and_load_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("jwks.json");
+        let snapshot: Snapshot = Snapshot::new(path.clone(), Duration::from_secs(60));
+
+        assert!(snapshot.load().is_none());
+
+        snapshot.save(key_set());
+
+        for _ in 0..100 {
+            if path.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let (loaded, _) = snapshot.load().unwrap();
+        assert!(loaded.get_key("test-key").is_ok());
+        assert!(!dir.path().join("jwks.json.tmp").exists());
+    }
+
+    #[test]
+    fn load_ignores_old_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("jwks.json");
+
+        let file: SnapshotFile = SnapshotFile {
+            fetched_at_millis: chrono::Utc::now().timestamp_millis() - 120_000,
+            key_set: key_set(),
+        };
+        write_atomically(&path, &file).unwrap();
+
+        assert!(Snapshot::new(path.clone(), Duration::from_secs(60))
+            .load()
+            .is_none());
+        assert!(Snapshot::new(path, Duration::from_secs(180))
+            .load()
+            .is_some());
+    }
+
+    #[test]
+    fn load_ignores_invalid_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("jwks.json");
+        std::fs::write(&path, "not json").unwrap();
+
+        assert!(Snapshot::new(path, Duration::from_secs(60))
+            .load()
+            .is_none());
+    }
+}
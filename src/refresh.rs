@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::cache::Cache;
+use crate::source::{self, JwksSource, Validators};
+
+// Shortest time between two background refreshes, whatever the time to live and the jitter, so
+// that a zero time to live (e.g. a `Cache-Control: no-cache` key set) cannot cause a tight loop.
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+// Longest time between two attempts after failed background refreshes, which start from
+// `MIN_REFRESH_INTERVAL` and double at each failure.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Task refreshing the cached key set before it expires. The task is aborted when this handle
+/// is dropped, that is when the last clone of the owning `JwksClient` goes away.
+pub(crate) struct BackgroundRefresh {
+    handle: JoinHandle<()>,
+}
+
+impl BackgroundRefresh {
+    /// Spawns the refresh task on the current tokio runtime.
+    ///
+    /// The key set is fetched right away and then every `ttl_fraction` of its time to live,
+    /// randomly shifted by up to `jitter` of that interval, but never more often than once per
+    /// second. A failed refresh is retried sooner, after a delay doubling from one second up to
+    /// thirty, so that a transient failure doesn't let the key set expire.
+    pub(crate) fn spawn<T: JwksSource + Send + Sync + 'static>(
+        source: Arc<T>,
+        cache: Cache,
+        ttl_fraction: f64,
+        jitter: f64,
+    ) -> Self {
+        let handle: JoinHandle<()> = tokio::spawn(async move {
+            let mut failures: u32 = 0;
+
+            loop {
+                let interval: Duration = match refresh(source.as_ref(), &cache).await {
+                    Some(ttl) => {
+                        failures = 0;
+                        ttl.mul_f64(ttl_fraction)
+                    }
+                    None => {
+                        failures = failures.saturating_add(1);
+                        retry_delay(failures).min(cache.time_to_live().mul_f64(ttl_fraction))
+                    }
+                };
+
+                tokio::time::sleep(with_jitter(interval, jitter).max(MIN_REFRESH_INTERVAL)).await;
+            }
+        });
+
+        Self { handle }
+    }
+
+    /// Spawns a task refreshing the key set every time the source reports that its keys changed.
+    /// The task ends when the source stops sending notifications.
+    pub(crate) fn spawn_on_change<T: JwksSource + Send + Sync + 'static>(
+        source: Arc<T>,
+        cache: Cache,
+        mut changes: watch::Receiver<()>,
+    ) -> Self {
+        let handle: JoinHandle<()> = tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                refresh(source.as_ref(), &cache).await;
+            }
+        });
+
+        Self { handle }
+    }
+}
+
+impl Drop for BackgroundRefresh {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Returns the time to live of the refreshed entry, or `None` if the refresh failed.
+#[tracing::instrument(skip_all)]
+async fn refresh<T: JwksSource + Sync>(source: &T, cache: &Cache) -> Option<Duration> {
+    let validators: Option<Validators> = cache.validators().await;
+
+    match source::fetch_keys_since(source, validators.as_ref()).await {
+        Ok(fetched) => Some(cache.store(fetched).await),
+        Err(error) => {
+            tracing::warn!(%error, "background refresh of the key set failed");
+            None
+        }
+    }
+}
+
+/// The delay before retrying after the given number of failed refreshes in a row.
+fn retry_delay(failures: u32) -> Duration {
+    let factor: u32 = 2u32.saturating_pow(failures.saturating_sub(1));
+    MIN_REFRESH_INTERVAL
+        .saturating_mul(factor)
+        .min(MAX_RETRY_DELAY)
+}
+
+/// Randomly shifts the interval by up to `jitter` of its length, in both directions.
+pub(crate) fn with_jitter(interval: Duration, jitter: f64) -> Duration {
+    if jitter <= 0.0 {
+        return interval;
+    }
+
+    let factor: f64 = 1.0 + rand::random_range(-jitter..=jitter);
+    interval.mul_f64(factor.max(0.0))
+}
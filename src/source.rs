@@ -1,96 +1,303 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
-use reqwest::{Request, Url};
+use tokio::sync::watch;
 
-use crate::error::Error;
 use crate::keyset::JsonWebKeySet;
 use crate::JwksClientError;
 
-const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
-const TIMEOUT: Duration = Duration::from_secs(10);
+pub use circuit_breaker::{
+    CircuitBreakerHandle, CircuitBreakerSource, CircuitBreakerSourceBuilder, CircuitState,
+};
+pub use fallback::{FallbackSource, FallbackSourceBuilder};
+#[cfg(feature = "http")]
+pub use fetcher::HttpFetcher;
+pub use file::{FileSource, FileSourceBuilder};
+pub use merged::{DuplicateKeyPolicy, MergedSource, MergedSourceBuilder};
+#[cfg(feature = "http")]
+pub use oidc::{OidcDiscoverySource, OidcDiscoverySourceBuilder};
+#[cfg(feature = "http")]
+pub use retry::{RetryPolicy, RetryableError};
+pub use static_source::{StaticSource, StaticSourceHandle};
+#[cfg(feature = "http")]
+pub use web::{WebSource, WebSourceBuilder};
+
+mod circuit_breaker;
+mod fallback;
+#[cfg(feature = "http")]
+mod fetcher;
+mod file;
+mod merged;
+#[cfg(feature = "http")]
+mod oidc;
+#[cfg(feature = "http")]
+mod retry;
+mod static_source;
+#[cfg(feature = "http")]
+mod web;
 
 #[cfg_attr(test, mockall::automock)]
 #[async_trait]
 pub trait JwksSource {
     async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError>;
-}
 
-pub struct WebSource {
-    client: reqwest::Client,
-    url: Url,
-}
+    /// The issuer the keys belong to, if the source knows it. When present, `JwksClient::decode`
+    /// requires the `iss` claim of the token to match it.
+    fn issuer(&self) -> Option<String> {
+        None
+    }
+
+    /// Every issuer the keys belong to, for sources serving the keys of several issuers. When not
+    /// empty, `JwksClient::decode` requires the `iss` claim of the token to be one of them.
+    ///
+    /// The default implementation returns the [`issuer`](Self::issuer), if any.
+    fn issuers(&self) -> Vec<String> {
+        self.issuer().into_iter().collect()
+    }
+
+    /// Fetches the keys along with how long they can be cached, if the source knows it.
+    ///
+    /// The default implementation calls [`fetch_keys`](Self::fetch_keys) without any freshness
+    /// information, so the cache falls back to its configured time to live.
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_keys().await.map(FetchedKeySet::from)
+    }
 
-impl WebSource {
-    pub fn builder() -> WebSourceBuilder {
-        WebSourceBuilder::new()
+    /// Fetches the keys unless they didn't change since the key set the validators were returned
+    /// with, in which case the key set is [`not_modified`](FetchedKeySet::not_modified). Sources
+    /// must ignore validators they didn't return themselves, see [`Validators::origin`].
+    ///
+    /// The default implementation ignores the validators and calls
+    /// [`fetch_keys_with_freshness`](Self::fetch_keys_with_freshness).
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        let _ = validators;
+        self.fetch_keys_with_freshness().await
     }
 
-    pub fn url(&self) -> &Url {
-        &self.url
+    /// Notifications sent by the source when its keys changed, if it can detect it. The client
+    /// built with `JwksClient::builder` refreshes the cached key set on each of them.
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        None
     }
 }
 
+/// A type-erased source, e.g. to handle different kinds of sources in the same collection.
+pub type BoxedJwksSource = Box<dyn JwksSource + Send + Sync>;
+
 #[async_trait]
-impl JwksSource for WebSource {
-    #[tracing::instrument(skip(self), fields(url = %self.url))]
+impl JwksSource for BoxedJwksSource {
     async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
-        fetch_keys(self).await.map_err(JwksClientError::from)
+        self.as_ref().fetch_keys().await
+    }
+
+    fn issuer(&self) -> Option<String> {
+        self.as_ref().issuer()
+    }
+
+    fn issuers(&self) -> Vec<String> {
+        self.as_ref().issuers()
+    }
+
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        self.as_ref().fetch_keys_with_freshness().await
+    }
+
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        self.as_ref().fetch_keys_if_modified(validators).await
+    }
+
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        self.as_ref().changes()
+    }
+}
+
+/// Notifies the returned receiver whenever one of the given receivers is notified, e.g. for a
+/// source made of several sources. Forwarding requires a tokio runtime: without one, the changes
+/// are ignored.
+pub(crate) fn merge_changes(
+    mut receivers: Vec<watch::Receiver<()>>,
+) -> Option<watch::Receiver<()>> {
+    if receivers.len() <= 1 {
+        return receivers.pop();
+    }
+
+    if tokio::runtime::Handle::try_current().is_err() {
+        tracing::warn!("not within a tokio runtime, changes of the sources are ignored");
+        return None;
+    }
+
+    let (sender, receiver) = watch::channel(());
+    let sender: Arc<watch::Sender<()>> = Arc::new(sender);
+
+    for mut changes in receivers {
+        let sender: Arc<watch::Sender<()>> = sender.clone();
+
+        // Ends when the source stops sending notifications or nobody listens anymore
+        tokio::spawn(async move {
+            while changes.changed().await.is_ok() {
+                if sender.send(()).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    Some(receiver)
+}
+
+/// Fetches the keys, conditionally if the validators of the cached key set are known.
+pub(crate) async fn fetch_keys_since<T: JwksSource + Sync + ?Sized>(
+    source: &T,
+    validators: Option<&Validators>,
+) -> Result<FetchedKeySet, JwksClientError> {
+    match validators {
+        Some(validators) => source.fetch_keys_if_modified(validators).await,
+        None => source.fetch_keys_with_freshness().await,
+    }
+}
+
+/// A key set fetched by a [`JwksSource`] together with its freshness metadata.
+///
+/// A source can also report that the key set didn't change since the one its validators belong
+/// to (e.g. after a `304 Not Modified` response), in which case the cache keeps the current keys
+/// and only extends their lifetime.
+#[derive(Debug, Clone)]
+pub struct FetchedKeySet {
+    set: Option<JsonWebKeySet>,
+    max_age: Option<Duration>,
+    validators: Option<Validators>,
+}
+
+impl FetchedKeySet {
+    pub fn new(set: JsonWebKeySet) -> Self {
+        Self {
+            set: Some(set),
+            max_age: None,
+            validators: None,
+        }
+    }
+
+    /// The key set didn't change since the one the validators passed to
+    /// [`JwksSource::fetch_keys_if_modified`] belong to. Those validators should be attached with
+    /// [`with_validators`](Self::with_validators), so that the cache only keeps its key set when
+    /// they still match it.
+    pub fn not_modified() -> Self {
+        Self {
+            set: None,
+            max_age: None,
+            validators: None,
+        }
+    }
+
+    /// Sets how long the key set can be considered fresh.
+    pub fn with_max_age(self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..self
+        }
+    }
+
+    /// Sets the validators of the key set, passed back to the source on the next fetch.
+    pub fn with_validators(self, validators: Validators) -> Self {
+        Self {
+            validators: Some(validators),
+            ..self
+        }
+    }
+
+    pub fn is_not_modified(&self) -> bool {
+        self.set.is_none()
+    }
+
+    /// Returns the fetched key set, or `None` if it was not modified.
+    pub fn set(&self) -> Option<&JsonWebKeySet> {
+        self.set.as_ref()
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    pub fn validators(&self) -> Option<&Validators> {
+        self.validators.as_ref()
+    }
+
+    pub fn into_set(self) -> Option<JsonWebKeySet> {
+        self.set
+    }
+
+    pub(crate) fn into_parts(self) -> (Option<JsonWebKeySet>, Option<Validators>) {
+        (self.set, self.validators)
     }
 }
 
-async fn fetch_keys(source: &WebSource) -> Result<JsonWebKeySet, Error> {
-    let request: Request = source.client.get(source.url.clone()).build()?;
-    let keys: JsonWebKeySet = source
-        .client
-        .execute(request)
-        .await?
-        .error_for_status()?
-        .json()
-        .await?;
-
-    Ok(keys)
+impl From<JsonWebKeySet> for FetchedKeySet {
+    fn from(set: JsonWebKeySet) -> Self {
+        Self::new(set)
+    }
 }
 
-pub struct WebSourceBuilder {
-    client_builder: reqwest::ClientBuilder,
-    timeout_opt: Option<Duration>,
-    connect_timeout_opt: Option<Duration>,
+/// Validators of a fetched key set (e.g. its `ETag`), kept by the cache alongside the key set and
+/// passed back to [`JwksSource::fetch_keys_if_modified`] to make the next fetch conditional.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validators {
+    origin: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    // Validators of each inner source, for sources made of several sources
+    parts: Vec<Option<Validators>>,
 }
 
-impl WebSourceBuilder {
-    fn new() -> Self {
+impl Validators {
+    /// `origin` identifies where the key set comes from (e.g. the URL of a web source), so that a
+    /// source can tell apart the validators of a key set returned by another source.
+    pub fn new(origin: impl Into<String>) -> Self {
         Self {
-            client_builder: reqwest::ClientBuilder::default(),
-            timeout_opt: None,
-            connect_timeout_opt: None,
+            origin: origin.into(),
+            etag: None,
+            last_modified: None,
+            parts: vec![],
         }
     }
 
-    pub fn with_timeout(self, timeout: Duration) -> Self {
+    pub fn with_etag(self, etag: impl Into<String>) -> Self {
         Self {
-            timeout_opt: Some(timeout),
+            etag: Some(etag.into()),
             ..self
         }
     }
 
-    pub fn with_connect_timeout(self, connect_timeout: Duration) -> Self {
+    pub fn with_last_modified(self, last_modified: impl Into<String>) -> Self {
         Self {
-            connect_timeout_opt: Some(connect_timeout),
+            last_modified: Some(last_modified.into()),
             ..self
         }
     }
 
-    pub fn build(self, url: Url) -> Result<WebSource, reqwest::Error> {
-        let timeout: Duration = self.timeout_opt.unwrap_or(TIMEOUT);
-        let connect_timeout: Duration = self.connect_timeout_opt.unwrap_or(CONNECT_TIMEOUT);
-        Ok(WebSource {
-            url,
-            client: self
-                .client_builder
-                .timeout(timeout)
-                .connect_timeout(connect_timeout)
-                .build()?,
-        })
+    pub fn origin(&self) -> &str {
+        &self.origin
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    pub fn last_modified(&self) -> Option<&str> {
+        self.last_modified.as_deref()
+    }
+
+    pub(crate) fn with_parts(self, parts: Vec<Option<Validators>>) -> Self {
+        Self { parts, ..self }
+    }
+
+    pub(crate) fn parts(&self) -> &[Option<Validators>] {
+        &self.parts
     }
 }
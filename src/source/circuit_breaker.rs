@@ -0,0 +1,350 @@
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::watch;
+
+use crate::error::Error;
+use crate::keyset::JsonWebKeySet;
+use crate::source::{FetchedKeySet, JwksSource, Validators};
+use crate::JwksClientError;
+
+const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// The state of a [`CircuitBreakerSource`], e.g. to be reported by a health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Fetches go through to the wrapped source.
+    Closed,
+    /// Fetches fail right away until the cooldown elapses.
+    Open,
+    /// The cooldown elapsed: the next fetch is let through to probe the wrapped source.
+    HalfOpen,
+}
+
+/// Source wrapping another [`JwksSource`] to stop calling it while it keeps failing.
+///
+/// After `failure_threshold` consecutive failures the circuit opens and fetches fail fast with
+/// [`ErrorKind::CircuitOpen`](crate::ErrorKind::CircuitOpen) for the cooldown. Then a single
+/// fetch is let through: the circuit closes again if it succeeds, otherwise it stays open for
+/// another cooldown.
+///
+/// The state can be read through a [`CircuitBreakerHandle`], e.g. once the source is owned by a
+/// client.
+pub struct CircuitBreakerSource<T> {
+    source: T,
+    failure_threshold: u32,
+    cooldown_millis: i64,
+    state: Arc<Mutex<State>>,
+}
+
+/// Reads the state of the [`CircuitBreakerSource`] it has been created from.
+#[derive(Clone)]
+pub struct CircuitBreakerHandle {
+    state: Arc<Mutex<State>>,
+}
+
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until_millis: i64,
+    },
+    /// A probe is in flight. Another one is let through if it doesn't complete by the deadline,
+    /// e.g. because its future has been dropped.
+    HalfOpen {
+        probe_until_millis: i64,
+    },
+}
+
+impl<T: JwksSource + Send + Sync> CircuitBreakerSource<T> {
+    pub fn builder() -> CircuitBreakerSourceBuilder<T> {
+        CircuitBreakerSourceBuilder::new()
+    }
+
+    pub fn state(&self) -> CircuitState {
+        circuit_state(&self.state)
+    }
+
+    pub fn handle(&self) -> CircuitBreakerHandle {
+        CircuitBreakerHandle {
+            state: self.state.clone(),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.source
+    }
+
+    /// Checks whether a fetch can go through, moving an expired open circuit to half open.
+    fn acquire(&self) -> Result<(), Error> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now: i64 = now_millis();
+
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until_millis }
+            | State::HalfOpen {
+                probe_until_millis: until_millis,
+            } if until_millis <= now => {
+                tracing::debug!("probing the source");
+                *state = State::HalfOpen {
+                    probe_until_millis: now + self.cooldown_millis,
+                };
+                Ok(())
+            }
+            State::Open { .. } | State::HalfOpen { .. } => Err(Error::CircuitOpen),
+        }
+    }
+
+    fn record<R>(&self, result: &Result<R, JwksClientError>) {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        *state = match (&*state, result) {
+            (State::Closed { .. }, Ok(_)) => State::Closed { failures: 0 },
+            (_, Ok(_)) => {
+                tracing::info!("source recovered, closing the circuit");
+                State::Closed { failures: 0 }
+            }
+            (State::Closed { failures }, Err(_)) if failures + 1 < self.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, Err(error)) => {
+                tracing::warn!(%error, cooldown_millis = self.cooldown_millis, "opening the circuit");
+                State::Open {
+                    until_millis: now_millis() + self.cooldown_millis,
+                }
+            }
+        };
+    }
+}
+
+impl CircuitBreakerHandle {
+    pub fn state(&self) -> CircuitState {
+        circuit_state(&self.state)
+    }
+}
+
+#[async_trait]
+impl<T: JwksSource + Send + Sync> JwksSource for CircuitBreakerSource<T> {
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        self.acquire()?;
+        let result: Result<JsonWebKeySet, JwksClientError> = self.source.fetch_keys().await;
+        self.record(&result);
+        result
+    }
+
+    fn issuer(&self) -> Option<String> {
+        self.source.issuer()
+    }
+
+    fn issuers(&self) -> Vec<String> {
+        self.source.issuers()
+    }
+
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        self.acquire()?;
+        let result: Result<FetchedKeySet, JwksClientError> =
+            self.source.fetch_keys_with_freshness().await;
+        self.record(&result);
+        result
+    }
+
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        self.acquire()?;
+        let result: Result<FetchedKeySet, JwksClientError> =
+            self.source.fetch_keys_if_modified(validators).await;
+        self.record(&result);
+        result
+    }
+
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        self.source.changes()
+    }
+}
+
+pub struct CircuitBreakerSourceBuilder<T> {
+    failure_threshold_opt: Option<u32>,
+    cooldown_opt: Option<Duration>,
+    t: PhantomData<*const T>,
+}
+
+impl<T: JwksSource + Send + Sync> CircuitBreakerSourceBuilder<T> {
+    fn new() -> Self {
+        Self {
+            failure_threshold_opt: None,
+            cooldown_opt: None,
+            t: PhantomData,
+        }
+    }
+
+    /// Number of consecutive failures opening the circuit. Defaults to 5.
+    pub fn with_failure_threshold(self, failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold_opt: Some(failure_threshold.max(1)),
+            ..self
+        }
+    }
+
+    /// How long the circuit stays open before probing the source again. Defaults to 30 seconds.
+    pub fn with_cooldown(self, cooldown: Duration) -> Self {
+        Self {
+            cooldown_opt: Some(cooldown),
+            ..self
+        }
+    }
+
+    pub fn build(self, source: T) -> CircuitBreakerSource<T> {
+        let cooldown: Duration = self.cooldown_opt.unwrap_or(DEFAULT_COOLDOWN);
+
+        CircuitBreakerSource {
+            source,
+            failure_threshold: self
+                .failure_threshold_opt
+                .unwrap_or(DEFAULT_FAILURE_THRESHOLD),
+            cooldown_millis: i64::try_from(cooldown.as_millis()).unwrap_or(i64::MAX / 2),
+            state: Arc::new(Mutex::new(State::Closed { failures: 0 })),
+        }
+    }
+}
+
+fn circuit_state(state: &Mutex<State>) -> CircuitState {
+    match *state.lock().unwrap_or_else(|e| e.into_inner()) {
+        State::Closed { .. } => CircuitState::Closed,
+        State::Open { until_millis } if until_millis > now_millis() => CircuitState::Open,
+        State::Open { .. } => CircuitState::HalfOpen,
+        State::HalfOpen { .. } => CircuitState::HalfOpen,
+    }
+}
+
+fn now_millis() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::error::{Error, ErrorKind};
+    use crate::source::{
+        CircuitBreakerHandle, CircuitBreakerSource, CircuitState, FetchedKeySet, JwksSource,
+        MockJwksSource,
+    };
+    use crate::{JsonWebKeySet, JwksClient, JwksClientError};
+
+    fn unavailable() -> JwksClientError {
+        Error::InvalidOperation("unavailable".to_string()).into()
+    }
+
+    #[tokio::test]
+    async fn circuit_opens_after_consecutive_failures() {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .times(3)
+            .returning(|| Err(unavailable()));
+
+        let breaker = CircuitBreakerSource::builder()
+            .with_failure_threshold(3)
+            .with_cooldown(Duration::from_secs(60))
+            .build(source);
+
+        for _ in 0..3 {
+            assert_eq!(CircuitState::Closed, breaker.state());
+            let error = breaker.fetch_keys_with_freshness().await.err().unwrap();
+            assert_ne!(ErrorKind::CircuitOpen, error.kind());
+        }
+
+        assert_eq!(CircuitState::Open, breaker.state());
+
+        // The source is not called anymore
+        let error = breaker.fetch_keys_with_freshness().await.err().unwrap();
+        assert_eq!(ErrorKind::CircuitOpen, error.kind());
+    }
+
+    #[tokio::test]
+    async fn successful_probe_closes_the_circuit() {
+        let mut source = MockJwksSource::new();
+        let mut sequence = mockall::Sequence::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .times(1)
+            .in_sequence(&mut sequence)
+            .returning(|| Err(unavailable()));
+        source
+            .expect_fetch_keys_with_freshness()
+            .times(2)
+            .in_sequence(&mut sequence)
+            .returning(|| Ok(FetchedKeySet::new(JsonWebKeySet::empty())));
+
+        let breaker = CircuitBreakerSource::builder()
+            .with_failure_threshold(1)
+            .with_cooldown(Duration::from_millis(50))
+            .build(source);
+
+        assert!(breaker.fetch_keys_with_freshness().await.is_err());
+        assert_eq!(CircuitState::Open, breaker.state());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(CircuitState::HalfOpen, breaker.state());
+
+        assert!(breaker.fetch_keys_with_freshness().await.is_ok());
+        assert_eq!(CircuitState::Closed, breaker.state());
+        assert!(breaker.fetch_keys_with_freshness().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn failed_probe_opens_the_circuit_again() {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys()
+            .times(2)
+            .returning(|| Err(unavailable()));
+
+        let breaker = CircuitBreakerSource::builder()
+            .with_failure_threshold(1)
+            .with_cooldown(Duration::from_millis(50))
+            .build(source);
+
+        assert!(breaker.fetch_keys().await.is_err());
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+
+        let error = breaker.fetch_keys().await.err().unwrap();
+        assert_ne!(ErrorKind::CircuitOpen, error.kind());
+        assert_eq!(CircuitState::Open, breaker.state());
+
+        let error = breaker.fetch_keys().await.err().unwrap();
+        assert_eq!(ErrorKind::CircuitOpen, error.kind());
+    }
+
+    #[tokio::test]
+    async fn handle_reads_state_of_source_owned_by_client() {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .times(1)
+            .returning(|| Err(unavailable()));
+        source.expect_changes().returning(|| None);
+
+        let breaker = CircuitBreakerSource::builder()
+            .with_failure_threshold(1)
+            .with_cooldown(Duration::from_secs(60))
+            .build(source);
+        let handle: CircuitBreakerHandle = breaker.handle();
+        let client = JwksClient::builder().build(breaker);
+
+        assert_eq!(CircuitState::Closed, handle.state());
+        assert!(client.get("kid").await.is_err());
+        assert_eq!(CircuitState::Open, handle.state());
+    }
+}
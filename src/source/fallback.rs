@@ -0,0 +1,306 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::watch;
+
+use crate::error::Error;
+use crate::keyset::JsonWebKeySet;
+use crate::source::{self, FetchedKeySet, JwksSource, Validators};
+use crate::JwksClientError;
+
+type DynSource = dyn JwksSource + Send + Sync;
+type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Source trying several sources in order (e.g. a primary identity provider and its mirror),
+/// returning the keys of the first one that succeeds.
+///
+/// If every source fails, the error lists the failure of each of them. Optionally, the source
+/// that last succeeded is tried first for a while, so that a dead primary is not called on every
+/// refresh.
+///
+/// The issuer is the one of the source that served the keys last, and changes reported by any
+/// of the sources trigger a refresh.
+pub struct FallbackSource {
+    sources: Vec<Box<DynSource>>,
+    // Index of the source that served the keys last
+    served: AtomicUsize,
+    stickiness_millis: Option<i64>,
+    // Index of the source to try first, until the given time in millis
+    sticky: Mutex<Option<(usize, i64)>>,
+}
+
+impl FallbackSource {
+    pub fn builder() -> FallbackSourceBuilder {
+        FallbackSourceBuilder::new()
+    }
+
+    /// Indexes of the sources in the order they should be tried.
+    fn order(&self) -> Vec<usize> {
+        let sticky: Option<(usize, i64)> = *self.sticky.lock().unwrap_or_else(|e| e.into_inner());
+
+        match sticky {
+            Some((first, until_millis)) if until_millis > Utc::now().timestamp_millis() => {
+                std::iter::once(first)
+                    .chain((0..self.sources.len()).filter(|index: &usize| *index != first))
+                    .collect()
+            }
+            _ => (0..self.sources.len()).collect(),
+        }
+    }
+
+    fn succeeded(&self, index: usize) {
+        self.served.store(index, Ordering::SeqCst);
+        let mut sticky = self.sticky.lock().unwrap_or_else(|e| e.into_inner());
+
+        *sticky = match self.stickiness_millis {
+            // Going back to the preferred order is the default anyway
+            Some(_) if index == 0 => None,
+            Some(stickiness_millis) => {
+                Some((index, Utc::now().timestamp_millis() + stickiness_millis))
+            }
+            None => None,
+        };
+    }
+
+    async fn fetch_with<R, F>(&self, fetch: F) -> Result<R, JwksClientError>
+    where
+        R: Send,
+        F: for<'a> Fn(&'a DynSource) -> BoxFuture<'a, Result<R, JwksClientError>> + Sync,
+    {
+        let mut errors: Vec<JwksClientError> = Vec::with_capacity(self.sources.len());
+
+        for index in self.order() {
+            match fetch(self.sources[index].as_ref()).await {
+                Ok(result) => {
+                    self.succeeded(index);
+                    return Ok(result);
+                }
+                Err(error) => {
+                    tracing::warn!(source = index, %error, "fetching keys failed, trying the next source");
+                    errors.push(error);
+                }
+            }
+        }
+
+        Err(Error::AllSourcesFailed(errors).into())
+    }
+}
+
+#[async_trait]
+impl JwksSource for FallbackSource {
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        self.fetch_with(|source: &DynSource| source.fetch_keys())
+            .await
+    }
+
+    /// The issuer of the source that served the keys last, the first one until keys are fetched.
+    fn issuer(&self) -> Option<String> {
+        self.sources[self.served.load(Ordering::SeqCst)].issuer()
+    }
+
+    /// The issuers of the source that served the keys last, the first one until keys are fetched.
+    fn issuers(&self) -> Vec<String> {
+        self.sources[self.served.load(Ordering::SeqCst)].issuers()
+    }
+
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_with(|source: &DynSource| source.fetch_keys_with_freshness())
+            .await
+    }
+
+    /// Each source is given the validators, only the one that returned the cached key set makes
+    /// a conditional request.
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_with(|source: &DynSource| {
+            let validators: Validators = validators.clone();
+            Box::pin(async move { source.fetch_keys_if_modified(&validators).await })
+        })
+        .await
+    }
+
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        source::merge_changes(
+            self.sources
+                .iter()
+                .filter_map(|source| source.changes())
+                .collect(),
+        )
+    }
+}
+
+pub struct FallbackSourceBuilder {
+    sources: Vec<Box<DynSource>>,
+    stickiness_opt: Option<Duration>,
+}
+
+impl FallbackSourceBuilder {
+    fn new() -> Self {
+        Self {
+            sources: vec![],
+            stickiness_opt: None,
+        }
+    }
+
+    /// Adds a source, tried after the ones already added.
+    pub fn with_source<T: JwksSource + Send + Sync + 'static>(mut self, source: T) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Keeps trying first the source that last succeeded for the given time, instead of going
+    /// through the sources in order on every fetch.
+    pub fn with_stickiness(self, stickiness: Duration) -> Self {
+        Self {
+            stickiness_opt: Some(stickiness),
+            ..self
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if no source has been added.
+    pub fn build(self) -> FallbackSource {
+        assert!(!self.sources.is_empty(), "at least one source is required");
+
+        FallbackSource {
+            sources: self.sources,
+            served: AtomicUsize::new(0),
+            stickiness_millis: self.stickiness_opt.map(|stickiness: Duration| {
+                i64::try_from(stickiness.as_millis()).unwrap_or(i64::MAX / 2)
+            }),
+            sticky: Mutex::new(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::error::{Error, ErrorKind};
+    use crate::source::{
+        FallbackSource, FetchedKeySet, JwksSource, MockJwksSource, StaticSource, StaticSourceHandle,
+    };
+    use crate::{JsonWebKeySet, JwksClientError};
+
+    fn failing_source(message: &'static str, times: usize) -> MockJwksSource {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .times(times)
+            .returning(move || Err(Error::InvalidOperation(message.to_string()).into()));
+        source
+    }
+
+    fn working_source(times: usize) -> MockJwksSource {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .times(times)
+            .returning(|| Ok(FetchedKeySet::new(JsonWebKeySet::empty())));
+        source
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_from_first_working_source() {
+        let mut unused = MockJwksSource::new();
+        unused.expect_fetch_keys_with_freshness().never();
+
+        let source: FallbackSource = FallbackSource::builder()
+            .with_source(failing_source("primary", 2))
+            .with_source(working_source(2))
+            .with_source(unused)
+            .build();
+
+        assert!(source.fetch_keys_with_freshness().await.is_ok());
+        // Without stickiness the primary is tried again
+        assert!(source.fetch_keys_with_freshness().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_sticks_to_last_working_source() {
+        let source: FallbackSource = FallbackSource::builder()
+            .with_source(failing_source("primary", 1))
+            .with_source(working_source(3))
+            .with_stickiness(Duration::from_secs(60))
+            .build();
+
+        for _ in 0..3 {
+            assert!(source.fetch_keys_with_freshness().await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_reports_every_failure() {
+        let source: FallbackSource = FallbackSource::builder()
+            .with_source(failing_source("primary", 1))
+            .with_source(failing_source("mirror", 1))
+            .build();
+
+        let error: JwksClientError = source.fetch_keys_with_freshness().await.err().unwrap();
+        assert_eq!(ErrorKind::Fetch, error.kind());
+
+        let message: String = error.to_string();
+        assert!(message.contains("primary"), "{message}");
+        assert!(message.contains("mirror"), "{message}");
+    }
+
+    #[tokio::test]
+    async fn issuer_of_the_serving_source() {
+        let mut primary = failing_source("primary", 1);
+        primary
+            .expect_issuer()
+            .returning(|| Some("https://primary.example.com/".to_string()));
+        let mut mirror = working_source(1);
+        mirror
+            .expect_issuer()
+            .returning(|| Some("https://mirror.example.com/".to_string()));
+
+        let source: FallbackSource = FallbackSource::builder()
+            .with_source(primary)
+            .with_source(mirror)
+            .build();
+
+        assert_eq!(
+            Some("https://primary.example.com/".to_string()),
+            source.issuer()
+        );
+        assert!(source.fetch_keys_with_freshness().await.is_ok());
+        assert_eq!(
+            Some("https://mirror.example.com/".to_string()),
+            source.issuer()
+        );
+    }
+
+    #[tokio::test]
+    async fn changes_of_any_source_are_forwarded() {
+        let primary: StaticSource = StaticSource::new(JsonWebKeySet::empty());
+        let mirror: StaticSource = StaticSource::new(JsonWebKeySet::empty());
+        let mirror_handle: StaticSourceHandle = mirror.handle();
+
+        let source: FallbackSource = FallbackSource::builder()
+            .with_source(primary)
+            .with_source(mirror)
+            .build();
+
+        let mut changes = source.changes().unwrap();
+        mirror_handle.set_keys(JsonWebKeySet::empty());
+
+        let changed = tokio::time::timeout(Duration::from_secs(5), changes.changed()).await;
+        assert!(changed.unwrap().is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one source is required")]
+    fn build_without_sources_panics() {
+        let _ = FallbackSource::builder().build();
+    }
+}
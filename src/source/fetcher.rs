@@ -0,0 +1,46 @@
+use async_trait::async_trait;
+use http::{Request, Response};
+
+#[cfg(feature = "web-source")]
+use crate::error::Error;
+use crate::JwksClientError;
+
+/// HTTP client used by the web sources to send their `GET` requests, so that any HTTP stack (e.g.
+/// hyper or ureq) can be plugged in.
+///
+/// It is implemented for `reqwest::Client` with the `web-source` feature. With the `http` feature
+/// alone, the fetcher is given to
+/// [`WebSourceBuilder::build_with_http_fetcher`](crate::source::WebSourceBuilder::build_with_http_fetcher).
+#[async_trait]
+pub trait HttpFetcher {
+    /// Sends the request and reads the whole response body. Responses with an error status can
+    /// either be returned as they are or as an error: the source rejects them anyway.
+    ///
+    /// Errors should be created with [`JwksClientError::fetch`].
+    async fn get(&self, request: Request<()>) -> Result<Response<Vec<u8>>, JwksClientError>;
+}
+
+#[cfg(feature = "web-source")]
+#[async_trait]
+impl HttpFetcher for reqwest::Client {
+    async fn get(&self, request: Request<()>) -> Result<Response<Vec<u8>>, JwksClientError> {
+        let (parts, ()) = request.into_parts();
+
+        let response: reqwest::Response = self
+            .get(parts.uri.to_string())
+            .headers(parts.headers)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(Error::from)?;
+
+        let mut builder = Response::builder().status(response.status());
+        if let Some(headers) = builder.headers_mut() {
+            *headers = response.headers().clone();
+        }
+
+        let body: Vec<u8> = response.bytes().await.map_err(Error::from)?.to_vec();
+
+        Ok(builder.body(body).map_err(JwksClientError::fetch)?)
+    }
+}
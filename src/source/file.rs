@@ -0,0 +1,258 @@
+use std::fs::{self, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use crate::error::Error;
+use crate::keyset::JsonWebKeySet;
+use crate::source::JwksSource;
+use crate::JwksClientError;
+
+/// Source reading the key set from a local JSON file, e.g. mounted from a config map.
+///
+/// The file is read again on every fetch. In watch mode the file is also polled for changes, and
+/// the client refreshes its cache as soon as a change is detected.
+pub struct FileSource {
+    path: PathBuf,
+    watcher: Option<Watcher>,
+}
+
+struct Watcher {
+    changes: watch::Receiver<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl FileSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            watcher: None,
+        }
+    }
+
+    pub fn builder() -> FileSourceBuilder {
+        FileSourceBuilder::new()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[async_trait]
+impl JwksSource for FileSource {
+    #[tracing::instrument(skip(self), fields(path = %self.path.display()))]
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        let path: PathBuf = self.path.clone();
+        let content: Vec<u8> = tokio::task::spawn_blocking(move || fs::read(path))
+            .await
+            .map_err(io::Error::other)
+            .and_then(|result| result)
+            .map_err(|source| Error::ReadFile {
+                path: self.path.clone(),
+                source,
+            })?;
+
+        serde_json::from_slice(&content).map_err(|source| {
+            JwksClientError::from(Error::InvalidKeySetFile {
+                path: self.path.clone(),
+                source,
+            })
+        })
+    }
+
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        self.watcher
+            .as_ref()
+            .map(|watcher: &Watcher| watcher.changes.clone())
+    }
+}
+
+pub struct FileSourceBuilder {
+    poll_interval_opt: Option<Duration>,
+}
+
+impl FileSourceBuilder {
+    fn new() -> Self {
+        Self {
+            poll_interval_opt: None,
+        }
+    }
+
+    /// Checks the modification time and size of the file every `poll_interval`, notifying the
+    /// client when they change. The polling task is stopped when the source is dropped.
+    pub fn watch(self, poll_interval: Duration) -> Self {
+        Self {
+            poll_interval_opt: Some(poll_interval),
+        }
+    }
+
+    /// Builds the source. The file is not read until the first fetch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if watching is enabled and this is not called within a tokio runtime.
+    pub fn build(self, path: impl Into<PathBuf>) -> FileSource {
+        let path: PathBuf = path.into();
+
+        let watcher: Option<Watcher> = self.poll_interval_opt.map(|poll_interval: Duration| {
+            let (sender, changes) = watch::channel(());
+            // Taken right away, so that changes made before the task first runs are not missed
+            let initial_version: Option<(SystemTime, u64)> = version(&path);
+            let handle: JoinHandle<()> =
+                tokio::spawn(poll(path.clone(), initial_version, poll_interval, sender));
+            Watcher { changes, handle }
+        });
+
+        FileSource { path, watcher }
+    }
+}
+
+async fn poll(
+    path: PathBuf,
+    mut last_version: Option<(SystemTime, u64)>,
+    poll_interval: Duration,
+    sender: watch::Sender<()>,
+) {
+    loop {
+        tokio::time::sleep(poll_interval).await;
+
+        let current_version: Option<(SystemTime, u64)> = version(&path);
+
+        if current_version != last_version {
+            tracing::debug!(path = %path.display(), "key set file changed");
+            last_version = current_version;
+            sender.send_replace(());
+        }
+    }
+}
+
+/// Modification time and size of the file, if it can be read.
+fn version(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata: Metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use serde_json::{json, Value};
+
+    use crate::error::{Error, ErrorKind};
+    use crate::source::{FileSource, JwksSource};
+    use crate::{JwksClient, JwksClientError};
+
+    fn jwks(kid: &str) -> Value {
+        json!({
+            "keys": [{
+                "alg": "ES256",
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+                "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+                "kid": kid
+            }]
+        })
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("jwks.json");
+        std::fs::write(&path, jwks("test-key").to_string()).unwrap();
+
+        let source: FileSource = FileSource::new(&path);
+
+        assert!(source
+            .fetch_keys()
+            .await
+            .unwrap()
+            .get_key("test-key")
+            .is_ok());
+        assert!(source.changes().is_none());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_fails_on_malformed_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("jwks.json");
+        std::fs::write(&path, "{\"keys\": ").unwrap();
+
+        let error: JwksClientError = FileSource::new(&path).fetch_keys().await.err().unwrap();
+        assert_eq!(ErrorKind::Fetch, error.kind());
+
+        match error {
+            JwksClientError::Error(err) => match *err {
+                Error::InvalidKeySetFile {
+                    path: ref error_path,
+                    ..
+                } => {
+                    assert_eq!(&path, error_path)
+                }
+                _ => {
+                    eprintln!("{err}");
+                    unreachable!()
+                }
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_fails_on_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("missing.json");
+
+        match FileSource::new(&path).fetch_keys().await.err().unwrap() {
+            JwksClientError::Error(err) => match *err {
+                Error::ReadFile { ref source, .. } => {
+                    assert_eq!(std::io::ErrorKind::NotFound, source.kind())
+                }
+                _ => {
+                    eprintln!("{err}");
+                    unreachable!()
+                }
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn client_refreshes_keys_when_watched_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path: PathBuf = dir.path().join("jwks.json");
+        std::fs::write(&path, jwks("old-key").to_string()).unwrap();
+
+        let source: FileSource = FileSource::builder()
+            .watch(Duration::from_millis(10))
+            .build(&path);
+        let client: JwksClient<FileSource> = JwksClient::builder().build(source);
+
+        assert!(client.get("old-key").await.is_ok());
+
+        std::fs::write(&path, jwks("new-key-with-longer-id").to_string()).unwrap();
+
+        // The old key would be served from the cache until its expiration without a refresh
+        let mut refreshed: bool = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            if client.get("old-key").await.is_err() {
+                refreshed = true;
+                break;
+            }
+        }
+
+        assert!(refreshed);
+        assert!(client.get("new-key-with-longer-id").await.is_ok());
+    }
+}
@@ -0,0 +1,631 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::future::join_all;
+use tokio::sync::watch;
+
+use crate::error::Error;
+use crate::keyset::{JsonWebKey, JsonWebKeySet, SkippedKey};
+use crate::source::{self, FetchedKeySet, JwksSource, Validators};
+use crate::JwksClientError;
+
+type DynSource = dyn JwksSource + Send + Sync;
+
+// Origin of the validators of a merged key set, made of the validators of each source
+const ORIGIN: &str = "jwks_client_rs::MergedSource";
+
+/// What a [`MergedSource`] does with a key id found in more than one source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateKeyPolicy {
+    /// Keeps the key of the source added first.
+    #[default]
+    FirstWins,
+    /// Fails the whole fetch.
+    Error,
+    /// Keeps the keys of every source under their shared key id. A `JwksClient` tries each of
+    /// them in turn to verify a token with that `kid`.
+    KeepAll,
+}
+
+/// Source fetching several sources concurrently and serving the union of their key sets, e.g. to
+/// accept tokens from two identity providers during a migration.
+///
+/// By default the fetch fails if any of the sources fails. With partial results, the keys of the
+/// sources that succeeded are served, as long as at least one did.
+///
+/// The merged key set is fresh for the shortest max age reported by the sources, and is only
+/// fetched again in full when one of them changed. When every source knows its issuer, decoded
+/// tokens must be issued by one of them, and changes reported by any of the sources trigger a
+/// refresh.
+pub struct MergedSource {
+    sources: Vec<(String, Box<DynSource>)>,
+    duplicate_key_policy: DuplicateKeyPolicy,
+    partial_results: bool,
+}
+
+impl MergedSource {
+    pub fn builder() -> MergedSourceBuilder {
+        MergedSourceBuilder::new()
+    }
+
+    /// Fetches every source, conditionally when given validators of a key set merged by this
+    /// source. The merged key set is only reported as not modified if none of the sources changed.
+    async fn fetch_merged(
+        &self,
+        validators: Option<&Validators>,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        let parts: Option<&[Option<Validators>]> = validators
+            .filter(|validators: &&Validators| {
+                validators.origin() == ORIGIN && validators.parts().len() == self.sources.len()
+            })
+            .map(Validators::parts);
+
+        let mut results: Vec<Result<FetchedKeySet, JwksClientError>> =
+            join_all(self.sources.iter().enumerate().map(|(index, (_, source))| {
+                let part: Option<&Validators> = parts.and_then(|parts| parts[index].as_ref());
+                source::fetch_keys_since(source.as_ref(), part)
+            }))
+            .await;
+
+        let max_age: Option<Duration> = results
+            .iter()
+            .filter_map(|result| result.as_ref().ok()?.max_age())
+            .min();
+
+        let not_modified = |result: &Result<FetchedKeySet, JwksClientError>| {
+            result.as_ref().is_ok_and(FetchedKeySet::is_not_modified)
+        };
+
+        if let Some(validators) = validators.filter(|_| results.iter().all(not_modified)) {
+            let fetched: FetchedKeySet =
+                FetchedKeySet::not_modified().with_validators(validators.clone());
+            return Ok(with_max_age(fetched, max_age));
+        }
+
+        // Another source changed: the unchanged ones are needed in full to merge them again
+        for ((_, source), result) in self.sources.iter().zip(results.iter_mut()) {
+            if not_modified(result) {
+                *result = source.fetch_keys_with_freshness().await;
+            }
+        }
+
+        let mut key_sets: Vec<(&str, JsonWebKeySet)> = Vec::with_capacity(results.len());
+        let mut parts: Vec<Option<Validators>> = Vec::with_capacity(results.len());
+        let mut errors: Vec<(String, JwksClientError)> = vec![];
+
+        for ((name, _), result) in self.sources.iter().zip(results) {
+            match result.map(FetchedKeySet::into_parts) {
+                Ok((key_set, validators)) => {
+                    key_sets.push((name, key_set.unwrap_or_else(JsonWebKeySet::empty)));
+                    parts.push(validators);
+                }
+                Err(error) => {
+                    tracing::warn!(source = name, %error, "fetching keys failed");
+                    errors.push((name.clone(), error));
+                    parts.push(None);
+                }
+            }
+        }
+
+        if !errors.is_empty() && (!self.partial_results || key_sets.is_empty()) {
+            return Err(Error::SourcesFailed(errors).into());
+        }
+
+        let fetched: FetchedKeySet = FetchedKeySet::new(self.merge(key_sets)?);
+        let fetched: FetchedKeySet = if parts.iter().any(Option::is_some) {
+            fetched.with_validators(Validators::new(ORIGIN).with_parts(parts))
+        } else {
+            fetched
+        };
+
+        Ok(with_max_age(fetched, max_age))
+    }
+
+    fn merge(&self, key_sets: Vec<(&str, JsonWebKeySet)>) -> Result<JsonWebKeySet, Error> {
+        // Key id -> number of sources having it. Duplicates within a source are left as they are
+        let mut counts: HashMap<String, usize> = HashMap::new();
+
+        for (_, key_set) in &key_sets {
+            // Keys without `kid` cannot clash
+            let key_ids: HashSet<&str> = key_set
+                .iter()
+                .map(JsonWebKey::key_id)
+                .filter(|key_id: &&str| !key_id.is_empty())
+                .collect();
+
+            for key_id in key_ids {
+                *counts.entry(key_id.to_string()).or_default() += 1;
+            }
+        }
+
+        let mut keys: Vec<JsonWebKey> = Vec::with_capacity(counts.len());
+        let mut seen: HashSet<String> = HashSet::new();
+        let skipped_keys: Vec<SkippedKey> = key_sets
+            .iter()
+            .flat_map(|(_, key_set)| key_set.skipped_keys().iter().cloned())
+            .collect();
+
+        for (name, key_set) in key_sets {
+            for key in key_set.keys() {
+                let key_id: String = key.key_id().to_string();
+
+                if counts.get(&key_id).is_none_or(|count: &usize| *count == 1) {
+                    keys.push(key);
+                    continue;
+                }
+
+                match self.duplicate_key_policy {
+                    DuplicateKeyPolicy::FirstWins => {
+                        if seen.insert(key_id) {
+                            keys.push(key);
+                        } else {
+                            tracing::debug!(
+                                source = name,
+                                kid = key.key_id(),
+                                "duplicate key ignored"
+                            );
+                        }
+                    }
+                    DuplicateKeyPolicy::Error => return Err(Error::DuplicateKeyId(key_id)),
+                    DuplicateKeyPolicy::KeepAll => keys.push(key),
+                }
+            }
+        }
+
+        Ok(JsonWebKeySet::new(keys).with_skipped_keys(skipped_keys))
+    }
+}
+
+fn with_max_age(fetched: FetchedKeySet, max_age: Option<Duration>) -> FetchedKeySet {
+    match max_age {
+        Some(max_age) => fetched.with_max_age(max_age),
+        None => fetched,
+    }
+}
+
+#[async_trait]
+impl JwksSource for MergedSource {
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        let fetched: FetchedKeySet = self.fetch_merged(None).await?;
+        Ok(fetched.into_set().unwrap_or_else(JsonWebKeySet::empty))
+    }
+
+    /// The issuer of the sources, if they all report the same one.
+    fn issuer(&self) -> Option<String> {
+        let mut issuers = self.sources.iter().map(|(_, source)| source.issuer());
+        let first: Option<String> = issuers.next()?;
+
+        if issuers.all(|issuer: Option<String>| issuer == first) {
+            first
+        } else {
+            None
+        }
+    }
+
+    /// The issuers of all the sources, or none if any of the sources doesn't know its issuer since
+    /// its tokens could then be issued by anyone.
+    fn issuers(&self) -> Vec<String> {
+        let mut issuers: Vec<String> = vec![];
+        for (_, source) in &self.sources {
+            let source_issuers: Vec<String> = source.issuers();
+            if source_issuers.is_empty() {
+                return vec![];
+            }
+            for issuer in source_issuers {
+                if !issuers.contains(&issuer) {
+                    issuers.push(issuer);
+                }
+            }
+        }
+        issuers
+    }
+
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_merged(None).await
+    }
+
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_merged(Some(validators)).await
+    }
+
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        source::merge_changes(
+            self.sources
+                .iter()
+                .filter_map(|(_, source)| source.changes())
+                .collect(),
+        )
+    }
+}
+
+pub struct MergedSourceBuilder {
+    sources: Vec<(String, Box<DynSource>)>,
+    duplicate_key_policy_opt: Option<DuplicateKeyPolicy>,
+    partial_results_opt: Option<bool>,
+}
+
+impl MergedSourceBuilder {
+    fn new() -> Self {
+        Self {
+            sources: vec![],
+            duplicate_key_policy_opt: None,
+            partial_results_opt: None,
+        }
+    }
+
+    /// Adds a source. The name identifies it in logs and errors.
+    pub fn with_source<T: JwksSource + Send + Sync + 'static>(
+        mut self,
+        name: impl Into<String>,
+        source: T,
+    ) -> Self {
+        self.sources.push((name.into(), Box::new(source)));
+        self
+    }
+
+    /// Defaults to [`DuplicateKeyPolicy::FirstWins`].
+    pub fn with_duplicate_key_policy(self, duplicate_key_policy: DuplicateKeyPolicy) -> Self {
+        Self {
+            duplicate_key_policy_opt: Some(duplicate_key_policy),
+            ..self
+        }
+    }
+
+    /// Serves the keys of the sources that succeeded even if others failed. Disabled by default.
+    pub fn with_partial_results(self, partial_results: bool) -> Self {
+        Self {
+            partial_results_opt: Some(partial_results),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> MergedSource {
+        MergedSource {
+            sources: self.sources,
+            duplicate_key_policy: self.duplicate_key_policy_opt.unwrap_or_default(),
+            partial_results: self.partial_results_opt.unwrap_or(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, ErrorKind};
+    use crate::keyset::EcPublicJwk;
+    use std::time::Duration;
+
+    use crate::source::{
+        DuplicateKeyPolicy, FetchedKeySet, JwksSource, MergedSource, MergedSourceBuilder,
+        MockJwksSource, StaticSource, StaticSourceHandle, Validators,
+    };
+    use crate::{JsonWebKeySet, JwksClientError};
+
+    fn key_set(kids: &[&str], x: &str) -> JsonWebKeySet {
+        JsonWebKeySet::new(
+            kids.iter()
+                .map(|kid| EcPublicJwk::new(*kid, "P-256", x, "y").into())
+                .collect(),
+        )
+    }
+
+    fn source(kids: &'static [&'static str], x: &'static str) -> MockJwksSource {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .returning(move || Ok(FetchedKeySet::new(key_set(kids, x))));
+        source
+    }
+
+    fn failing_source() -> MockJwksSource {
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .returning(|| Err(Error::InvalidOperation("unavailable".to_string()).into()));
+        source
+    }
+
+    /// Source answering conditional fetches as not modified, unless `modified`.
+    fn conditional_source(kid: &'static str, max_age: Duration, modified: bool) -> MockJwksSource {
+        let fetched = move || {
+            FetchedKeySet::new(key_set(&[kid], kid))
+                .with_validators(Validators::new(kid).with_etag("v1"))
+                .with_max_age(max_age)
+        };
+
+        let mut source = MockJwksSource::new();
+        source
+            .expect_fetch_keys_with_freshness()
+            .returning(move || Ok(fetched()));
+        source
+            .expect_fetch_keys_if_modified()
+            .returning(move |validators: &Validators| {
+                assert_eq!(kid, validators.origin());
+
+                if modified {
+                    Ok(fetched())
+                } else {
+                    Ok(FetchedKeySet::not_modified().with_validators(validators.clone()))
+                }
+            });
+        source
+    }
+
+    fn builder() -> MergedSourceBuilder {
+        MergedSource::builder()
+            .with_source("first", source(&["a", "shared"], "first"))
+            .with_source("second", source(&["b", "shared"], "second"))
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_keeps_first_duplicate() {
+        let key_set: JsonWebKeySet = builder().build().fetch_keys().await.unwrap();
+
+        assert_eq!(3, key_set.iter().count());
+        assert!(key_set.get_key("a").is_ok());
+        assert!(key_set.get_key("b").is_ok());
+
+        let shared = key_set.get_key("shared").unwrap();
+        assert_eq!("first", shared.as_ec_public_key().unwrap().x());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_fails_on_duplicate() {
+        let error: JwksClientError = builder()
+            .with_duplicate_key_policy(DuplicateKeyPolicy::Error)
+            .build()
+            .fetch_keys()
+            .await
+            .err()
+            .unwrap();
+
+        match error {
+            JwksClientError::Error(err) => match *err {
+                Error::DuplicateKeyId(ref key_id) => assert_eq!("shared", key_id),
+                _ => {
+                    eprintln!("{err}");
+                    unreachable!()
+                }
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_keeps_every_duplicate_with_keep_all_policy() {
+        let key_set: JsonWebKeySet = MergedSource::builder()
+            .with_source("first", source(&["shared", "a"], "first"))
+            .with_source("second", source(&["shared"], "second"))
+            .with_duplicate_key_policy(DuplicateKeyPolicy::KeepAll)
+            .build()
+            .fetch_keys()
+            .await
+            .unwrap();
+
+        let shared: Vec<&str> = key_set
+            .iter()
+            .filter(|key| key.key_id() == "shared")
+            .map(|key| key.as_ec_public_key().unwrap().x())
+            .collect();
+        assert_eq!(vec!["first", "second"], shared);
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_ignores_duplicates_within_a_source() {
+        let key_set: JsonWebKeySet = MergedSource::builder()
+            .with_source("first", source(&["a", "a"], "first"))
+            .with_source("second", source(&["b"], "second"))
+            .with_duplicate_key_policy(DuplicateKeyPolicy::Error)
+            .build()
+            .fetch_keys()
+            .await
+            .unwrap();
+
+        assert_eq!(3, key_set.iter().count());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_keeps_keys_without_kid_of_every_source() {
+        let kid_less = || -> JsonWebKeySet {
+            serde_json::from_value(serde_json::json!({
+                "keys": [{ "kty": "EC", "crv": "P-256", "x": "x", "y": "y" }]
+            }))
+            .unwrap()
+        };
+
+        let key_set: JsonWebKeySet = MergedSource::builder()
+            .with_source("first", StaticSource::new(kid_less()))
+            .with_source("second", StaticSource::new(kid_less()))
+            .with_duplicate_key_policy(DuplicateKeyPolicy::Error)
+            .build()
+            .fetch_keys()
+            .await
+            .unwrap();
+
+        assert_eq!(2, key_set.iter().count());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_fails_when_a_source_fails() {
+        let error: JwksClientError = builder()
+            .with_source("third", failing_source())
+            .build()
+            .fetch_keys()
+            .await
+            .err()
+            .unwrap();
+
+        assert_eq!(ErrorKind::Fetch, error.kind());
+        assert!(error.to_string().contains("third"));
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_with_partial_results() {
+        let key_set: JsonWebKeySet = builder()
+            .with_source("third", failing_source())
+            .with_partial_results(true)
+            .build()
+            .fetch_keys()
+            .await
+            .unwrap();
+
+        assert_eq!(3, key_set.iter().count());
+
+        let merged: MergedSource = MergedSource::builder()
+            .with_source("first", failing_source())
+            .with_partial_results(true)
+            .build();
+        assert!(merged.fetch_keys().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_with_freshness_of_every_source() {
+        let merged: MergedSource = MergedSource::builder()
+            .with_source(
+                "first",
+                conditional_source("a", Duration::from_secs(60), false),
+            )
+            .with_source(
+                "second",
+                conditional_source("b", Duration::from_secs(30), false),
+            )
+            .build();
+
+        let fetched: FetchedKeySet = merged.fetch_keys_with_freshness().await.unwrap();
+        assert_eq!(Some(Duration::from_secs(30)), fetched.max_age());
+        assert_eq!(2, fetched.set().unwrap().iter().count());
+
+        let validators: Validators = fetched.validators().unwrap().clone();
+        let fetched: FetchedKeySet = merged.fetch_keys_if_modified(&validators).await.unwrap();
+        assert!(fetched.is_not_modified());
+        assert_eq!(Some(&validators), fetched.validators());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_in_full_when_a_source_changed() {
+        let merged: MergedSource = MergedSource::builder()
+            .with_source(
+                "first",
+                conditional_source("a", Duration::from_secs(60), false),
+            )
+            .with_source(
+                "second",
+                conditional_source("b", Duration::from_secs(30), true),
+            )
+            .build();
+
+        let fetched: FetchedKeySet = merged.fetch_keys_with_freshness().await.unwrap();
+        let validators: Validators = fetched.validators().unwrap().clone();
+
+        let fetched: FetchedKeySet = merged.fetch_keys_if_modified(&validators).await.unwrap();
+        let key_set: &JsonWebKeySet = fetched.set().unwrap();
+        assert!(key_set.get_key("a").is_ok());
+        assert!(key_set.get_key("b").is_ok());
+
+        // Validators of another source are not split among the sources
+        let fetched = merged.fetch_keys_if_modified(&Validators::new("a")).await;
+        assert!(!fetched.unwrap().is_not_modified());
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_keeps_skipped_keys() {
+        let mut with_skipped_key = MockJwksSource::new();
+        with_skipped_key
+            .expect_fetch_keys_with_freshness()
+            .returning(|| {
+                let key_set: JsonWebKeySet = serde_json::from_value(serde_json::json!({
+                    "keys": [{ "kty": "oct", "kid": "symmetric", "k": "c2VjcmV0" }]
+                }))
+                .unwrap();
+                Ok(FetchedKeySet::new(key_set))
+            });
+
+        let key_set: JsonWebKeySet = builder()
+            .with_source("third", with_skipped_key)
+            .build()
+            .fetch_keys()
+            .await
+            .unwrap();
+
+        assert_eq!(1, key_set.skipped_keys().len());
+        assert_eq!(Some("symmetric"), key_set.skipped_keys()[0].key_id());
+    }
+
+    #[test]
+    fn issuer_when_sources_agree() {
+        let with_issuer = |issuer: &'static str| {
+            let mut source = MockJwksSource::new();
+            source
+                .expect_issuer()
+                .returning(move || Some(issuer.to_string()));
+            source
+        };
+
+        let merged: MergedSource = MergedSource::builder()
+            .with_source("first", with_issuer("https://issuer.example.com/"))
+            .with_source("second", with_issuer("https://issuer.example.com/"))
+            .build();
+        assert_eq!(
+            Some("https://issuer.example.com/".to_string()),
+            merged.issuer()
+        );
+
+        let merged: MergedSource = MergedSource::builder()
+            .with_source("first", with_issuer("https://issuer.example.com/"))
+            .with_source("second", with_issuer("https://other.example.com/"))
+            .build();
+        assert_eq!(None, merged.issuer());
+    }
+
+    #[test]
+    fn issuers_of_every_source() {
+        let with_issuers = |issuers: &'static [&'static str]| {
+            let mut source = MockJwksSource::new();
+            source
+                .expect_issuers()
+                .returning(move || issuers.iter().map(ToString::to_string).collect());
+            source
+        };
+
+        let merged: MergedSource = MergedSource::builder()
+            .with_source("first", with_issuers(&["https://issuer.example.com/"]))
+            .with_source(
+                "second",
+                with_issuers(&["https://other.example.com/", "https://issuer.example.com/"]),
+            )
+            .build();
+        assert_eq!(
+            vec![
+                "https://issuer.example.com/".to_string(),
+                "https://other.example.com/".to_string()
+            ],
+            merged.issuers()
+        );
+
+        let merged: MergedSource = MergedSource::builder()
+            .with_source("first", with_issuers(&["https://issuer.example.com/"]))
+            .with_source("second", with_issuers(&[]))
+            .build();
+        assert!(merged.issuers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn changes_of_any_source_are_forwarded() {
+        let first: StaticSource = StaticSource::new(JsonWebKeySet::empty());
+        let second: StaticSource = StaticSource::new(JsonWebKeySet::empty());
+        let first_handle: StaticSourceHandle = first.handle();
+
+        let merged: MergedSource = MergedSource::builder()
+            .with_source("first", first)
+            .with_source("second", second)
+            .build();
+
+        let mut changes = merged.changes().unwrap();
+        first_handle.set_keys(JsonWebKeySet::empty());
+
+        let changed = tokio::time::timeout(Duration::from_secs(5), changes.changed()).await;
+        assert!(changed.unwrap().is_ok());
+    }
+}
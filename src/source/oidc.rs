@@ -0,0 +1,465 @@
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "web-source")]
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use url::Url;
+
+use crate::error::Error;
+use crate::keyset::JsonWebKeySet;
+use crate::source::web::DynFetcher;
+use crate::source::{
+    FetchedKeySet, HttpFetcher, JwksSource, RetryPolicy, Validators, WebSource, WebSourceBuilder,
+};
+use crate::JwksClientError;
+
+const DISCOVERY_PATH: &str = ".well-known/openid-configuration";
+
+/// Source resolving the JWKS url from the OpenID Connect discovery document of an issuer.
+///
+/// The `jwks_uri` is discovered on the first fetch and then reused. If fetching the keys fails
+/// the discovery is performed again, in case the provider moved its keys. The keys are fetched
+/// like a [`WebSource`] built with the same options would, retries included.
+/// See <https://openid.net/specs/openid-connect-discovery-1_0.html>
+pub struct OidcDiscoverySource {
+    fetcher: Arc<DynFetcher>,
+    retry_policy: Option<RetryPolicy>,
+    issuer: String,
+    discovery_url: Url,
+    jwks_source: Mutex<Option<Arc<WebSource>>>,
+}
+
+#[derive(Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    jwks_uri: Url,
+}
+
+impl OidcDiscoverySource {
+    pub fn builder() -> OidcDiscoverySourceBuilder {
+        OidcDiscoverySourceBuilder::new()
+    }
+
+    fn new(
+        fetcher: Arc<DynFetcher>,
+        retry_policy: Option<RetryPolicy>,
+        issuer: &str,
+        discovery_url: Url,
+    ) -> Self {
+        Self {
+            fetcher,
+            retry_policy,
+            issuer: issuer.to_string(),
+            discovery_url,
+            jwks_source: Mutex::new(None),
+        }
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// The last discovered JWKS url, if any.
+    pub fn jwks_uri(&self) -> Option<Url> {
+        self.current()
+            .map(|jwks_source: Arc<WebSource>| jwks_source.url().clone())
+    }
+
+    fn current(&self) -> Option<Arc<WebSource>> {
+        self.jwks_source
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    #[tracing::instrument(skip(self), fields(issuer = %self.issuer))]
+    async fn discover(&self) -> Result<Arc<WebSource>, JwksClientError> {
+        let request: http::Request<()> = http::Request::get(self.discovery_url.as_str())
+            .body(())
+            .map_err(JwksClientError::fetch)?;
+        let response: http::Response<Vec<u8>> = self.fetcher.get(request).await?;
+
+        if !response.status().is_success() {
+            return Err(Error::HttpStatus(response.status()).into());
+        }
+
+        let metadata: ProviderMetadata =
+            serde_json::from_slice(response.body()).map_err(Error::InvalidResponseBody)?;
+
+        if metadata.issuer != self.issuer {
+            return Err(Error::IssuerMismatch {
+                expected: self.issuer.clone(),
+                found: metadata.issuer,
+            }
+            .into());
+        }
+
+        let mut current = self.jwks_source.lock().unwrap_or_else(|e| e.into_inner());
+
+        match current.as_ref() {
+            Some(jwks_source) if *jwks_source.url() == metadata.jwks_uri => Ok(jwks_source.clone()),
+            _ => {
+                let jwks_source: Arc<WebSource> = Arc::new(WebSource::new(
+                    self.fetcher.clone(),
+                    metadata.jwks_uri,
+                    self.retry_policy.clone(),
+                ));
+                *current = Some(jwks_source.clone());
+                Ok(jwks_source)
+            }
+        }
+    }
+
+    async fn fetch_with<F, Fut, R>(&self, fetch: F) -> Result<R, JwksClientError>
+    where
+        F: Fn(Arc<WebSource>) -> Fut,
+        Fut: Future<Output = Result<R, JwksClientError>>,
+    {
+        let jwks_source: Arc<WebSource> = match self.current() {
+            Some(jwks_source) => jwks_source,
+            None => return fetch(self.discover().await?).await,
+        };
+
+        match fetch(jwks_source.clone()).await {
+            Ok(result) => Ok(result),
+            Err(error) => {
+                tracing::warn!(%error, "fetching keys failed, discovering the jwks_uri again");
+                let discovered: Arc<WebSource> = self.discover().await?;
+
+                if discovered.url() == jwks_source.url() {
+                    Err(error)
+                } else {
+                    fetch(discovered).await
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl JwksSource for OidcDiscoverySource {
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        self.fetch_with(|jwks_source: Arc<WebSource>| async move { jwks_source.fetch_keys().await })
+            .await
+    }
+
+    fn issuer(&self) -> Option<String> {
+        Some(self.issuer.clone())
+    }
+
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_with(|jwks_source: Arc<WebSource>| async move {
+            jwks_source.fetch_keys_with_freshness().await
+        })
+        .await
+    }
+
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        self.fetch_with(|jwks_source: Arc<WebSource>| async move {
+            jwks_source.fetch_keys_if_modified(validators).await
+        })
+        .await
+    }
+}
+
+pub struct OidcDiscoverySourceBuilder {
+    web_source_builder: WebSourceBuilder,
+}
+
+impl OidcDiscoverySourceBuilder {
+    fn new() -> Self {
+        Self {
+            web_source_builder: WebSource::builder(),
+        }
+    }
+
+    #[cfg(feature = "web-source")]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            web_source_builder: self.web_source_builder.with_timeout(timeout),
+        }
+    }
+
+    #[cfg(feature = "web-source")]
+    pub fn with_connect_timeout(self, connect_timeout: Duration) -> Self {
+        Self {
+            web_source_builder: self
+                .web_source_builder
+                .with_connect_timeout(connect_timeout),
+        }
+    }
+
+    /// See [`WebSourceBuilder::with_client`].
+    #[cfg(feature = "web-source")]
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        Self {
+            web_source_builder: self.web_source_builder.with_client(client),
+        }
+    }
+
+    /// See [`WebSourceBuilder::with_http_fetcher`].
+    #[cfg(feature = "web-source")]
+    pub fn with_http_fetcher<F: HttpFetcher + Send + Sync + 'static>(self, fetcher: F) -> Self {
+        Self {
+            web_source_builder: self.web_source_builder.with_http_fetcher(fetcher),
+        }
+    }
+
+    /// See [`WebSourceBuilder::with_retry_policy`]. Only fetching the keys is retried, not the
+    /// discovery.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Self {
+            web_source_builder: self.web_source_builder.with_retry_policy(retry_policy),
+        }
+    }
+
+    /// Builds the source for the given issuer (e.g. `https://{YOUR-TENANT}.eu.auth0.com/`).
+    /// The issuer must be exactly the one published in the discovery document.
+    #[cfg(feature = "web-source")]
+    pub fn build(self, issuer: &str) -> Result<OidcDiscoverySource, JwksClientError> {
+        let discovery_url: Url = discovery_url(issuer)?;
+        let (fetcher, retry_policy) = self.web_source_builder.build_parts().map_err(Error::from)?;
+
+        Ok(OidcDiscoverySource::new(
+            fetcher,
+            retry_policy,
+            issuer,
+            discovery_url,
+        ))
+    }
+
+    /// Builds the source for the given issuer, fetching the discovery document and the keys with
+    /// the given HTTP client. See [`WebSourceBuilder::build_with_http_fetcher`].
+    pub fn build_with_http_fetcher<F: HttpFetcher + Send + Sync + 'static>(
+        self,
+        fetcher: F,
+        issuer: &str,
+    ) -> Result<OidcDiscoverySource, JwksClientError> {
+        let discovery_url: Url = discovery_url(issuer)?;
+        let retry_policy: Option<RetryPolicy> = self.web_source_builder.into_retry_policy();
+
+        Ok(OidcDiscoverySource::new(
+            Arc::new(fetcher),
+            retry_policy,
+            issuer,
+            discovery_url,
+        ))
+    }
+}
+
+fn discovery_url(issuer: &str) -> Result<Url, JwksClientError> {
+    Url::parse(&format!("{}/", issuer.trim_end_matches('/')))
+        .and_then(|url: Url| url.join(DISCOVERY_PATH))
+        .map_err(|_| Error::InvalidIssuer(issuer.to_string()).into())
+}
+
+#[cfg(all(test, feature = "web-source"))]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use httpmock::prelude::*;
+    use serde_json::{json, Value};
+
+    use crate::error::Error;
+    use crate::source::{JwksSource, OidcDiscoverySource, RetryPolicy, WebSource};
+    use crate::JwksClientError;
+
+    fn jwks() -> Value {
+        json!({
+            "keys": [{
+                "alg": "ES256",
+                "kty": "EC",
+                "crv": "P-256",
+                "x": "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+                "y": "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+                "kid": "test-key"
+            }]
+        })
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_from_discovered_jwks_uri() {
+        let server = MockServer::start();
+        let issuer: String = server.url("/tenant/");
+
+        let discovery_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/tenant/.well-known/openid-configuration");
+
+            then.status(200).json_body(json!({
+                "issuer": issuer,
+                "jwks_uri": server.url("/tenant/keys"),
+            }));
+        });
+
+        let keys_mock = server.mock(|when, then| {
+            when.method(GET).path("/tenant/keys");
+
+            then.status(200).json_body(jwks());
+        });
+
+        let source: OidcDiscoverySource = OidcDiscoverySource::builder().build(&issuer).unwrap();
+
+        assert!(source
+            .fetch_keys()
+            .await
+            .unwrap()
+            .get_key("test-key")
+            .is_ok());
+        assert!(source.fetch_keys().await.is_ok());
+        assert_eq!(Some(issuer.clone()), JwksSource::issuer(&source));
+        assert_eq!(
+            Some(server.url("/tenant/keys")),
+            source.jwks_uri().map(String::from)
+        );
+
+        discovery_mock.assert_calls(1);
+        keys_mock.assert_calls(2);
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_fails_when_issuer_does_not_match() {
+        let server = MockServer::start();
+        let issuer: String = server.url("/tenant");
+
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/tenant/.well-known/openid-configuration");
+
+            then.status(200).json_body(json!({
+                "issuer": "https://evil.example.com",
+                "jwks_uri": server.url("/tenant/keys"),
+            }));
+        });
+
+        let source: OidcDiscoverySource = OidcDiscoverySource::builder().build(&issuer).unwrap();
+
+        match source.fetch_keys().await.err().unwrap() {
+            JwksClientError::Error(err) => match *err {
+                Error::IssuerMismatch { ref found, .. } => {
+                    assert_eq!("https://evil.example.com", found)
+                }
+                _ => {
+                    eprintln!("{err}");
+                    unreachable!()
+                }
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_fails_when_discovery_document_is_malformed() {
+        let server = MockServer::start();
+        let issuer: String = server.url("/tenant");
+
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/tenant/.well-known/openid-configuration");
+
+            then.status(200).body("not json");
+        });
+
+        let source: OidcDiscoverySource = OidcDiscoverySource::builder().build(&issuer).unwrap();
+
+        match source.fetch_keys().await.err().unwrap() {
+            JwksClientError::Error(err) => {
+                assert!(matches!(*err, Error::InvalidResponseBody(_)), "{err}")
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_discovers_again_when_fetching_fails() {
+        let server = MockServer::start();
+        let issuer: String = server.url("/tenant");
+
+        let mut discovery_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/tenant/.well-known/openid-configuration");
+
+            then.status(200).json_body(json!({
+                "issuer": issuer,
+                "jwks_uri": server.url("/tenant/old-keys"),
+            }));
+        });
+
+        let mut old_keys_mock = server.mock(|when, then| {
+            when.method(GET).path("/tenant/old-keys");
+
+            then.status(200).json_body(jwks());
+        });
+
+        let source: OidcDiscoverySource = OidcDiscoverySource::builder().build(&issuer).unwrap();
+        assert!(source.fetch_keys().await.is_ok());
+
+        discovery_mock.delete();
+        old_keys_mock.delete();
+
+        let discovery_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/tenant/.well-known/openid-configuration");
+
+            then.status(200).json_body(json!({
+                "issuer": issuer,
+                "jwks_uri": server.url("/tenant/new-keys"),
+            }));
+        });
+
+        let new_keys_mock = server.mock(|when, then| {
+            when.method(GET).path("/tenant/new-keys");
+
+            then.status(200).json_body(jwks());
+        });
+
+        assert!(source.fetch_keys().await.is_ok());
+
+        discovery_mock.assert_calls(1);
+        new_keys_mock.assert_calls(1);
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_retries_and_keeps_the_source_of_an_unchanged_jwks_uri() {
+        let server = MockServer::start();
+        let issuer: String = server.url("/tenant");
+
+        let discovery_mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/tenant/.well-known/openid-configuration");
+
+            then.status(200).json_body(json!({
+                "issuer": issuer,
+                "jwks_uri": server.url("/tenant/keys"),
+            }));
+        });
+
+        let keys_mock = server.mock(|when, then| {
+            when.method(GET).path("/tenant/keys");
+
+            then.status(503);
+        });
+
+        let retry_policy: RetryPolicy = RetryPolicy::new()
+            .with_max_attempts(2)
+            .with_base_delay(Duration::from_millis(1));
+        let source: OidcDiscoverySource = OidcDiscoverySource::builder()
+            .with_retry_policy(retry_policy)
+            .build(&issuer)
+            .unwrap();
+
+        assert!(source.fetch_keys().await.is_err());
+        let jwks_source: Arc<WebSource> = source.current().unwrap();
+
+        assert!(source.fetch_keys().await.is_err());
+        assert!(Arc::ptr_eq(&jwks_source, &source.current().unwrap()));
+
+        // Discovered on the first fetch, and again when the known jwks_uri fails
+        discovery_mock.assert_calls(2);
+        keys_mock.assert_calls(4);
+    }
+}
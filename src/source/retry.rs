@@ -0,0 +1,213 @@
+use std::time::Duration;
+
+use http::StatusCode;
+
+use crate::error::Error;
+use crate::refresh::with_jitter;
+use crate::JwksClientError;
+
+const MAX_ATTEMPTS: u32 = 3;
+const BASE_DELAY: Duration = Duration::from_millis(100);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+const JITTER: f64 = 0.2;
+
+/// Transport failures that can be retried by a [`RetryPolicy`]. They are only told apart for the
+/// `reqwest::Client` of the `web-source` feature, not for other [`HttpFetcher`]s.
+///
+/// [`HttpFetcher`]: crate::source::HttpFetcher
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RetryableError {
+    /// The connection to the server couldn't be established.
+    Connect,
+    /// The request or the response timed out.
+    Timeout,
+    /// The request failed while being sent, e.g. the connection was reset.
+    Request,
+    /// The response body couldn't be read completely.
+    Body,
+}
+
+/// How a `WebSource` retries failed fetches, waiting an exponentially growing delay between
+/// attempts.
+///
+/// By default a fetch is attempted up to 3 times, waiting 100ms and then 200ms (±20%), and
+/// retried on `408`, `429`, `500`, `502`, `503` and `504` responses, or when connecting, sending
+/// the request or reading the response fails or times out.
+///
+/// Lookups of a `JwksClient` answered by its cached keys don't wait for a refresh, but lookups
+/// that need one (an expired set or a missing key) wait for every attempt and delay of a failing
+/// refresh, up to `max_attempts` times the timeout plus the delays. Keep them short, the stale
+/// keys or a fallback source being better suited to ride out longer outages.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: f64,
+    retryable_statuses: Vec<StatusCode>,
+    // Only told apart for reqwest errors
+    #[cfg_attr(not(feature = "web-source"), allow(dead_code))]
+    retryable_errors: Vec<RetryableError>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: MAX_ATTEMPTS,
+            base_delay: BASE_DELAY,
+            max_delay: MAX_DELAY,
+            jitter: JITTER,
+            retryable_statuses: vec![
+                StatusCode::REQUEST_TIMEOUT,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retryable_errors: vec![
+                RetryableError::Connect,
+                RetryableError::Timeout,
+                RetryableError::Request,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total number of attempts, including the first one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_attempts` is 0.
+    pub fn with_max_attempts(self, max_attempts: u32) -> Self {
+        assert!(max_attempts > 0, "max_attempts must be greater than 0");
+        Self {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// Delay before the first retry, doubled for every following one.
+    pub fn with_base_delay(self, base_delay: Duration) -> Self {
+        Self { base_delay, ..self }
+    }
+
+    /// Upper bound of the delay between two attempts.
+    pub fn with_max_delay(self, max_delay: Duration) -> Self {
+        Self { max_delay, ..self }
+    }
+
+    /// Randomly shifts each delay by up to the given fraction of it, so that many clients don't
+    /// retry in lockstep.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `jitter` is not between 0 and 1.
+    pub fn with_jitter(self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "jitter must be between 0 and 1"
+        );
+        Self { jitter, ..self }
+    }
+
+    /// Response statuses worth another attempt.
+    pub fn with_retryable_statuses(self, retryable_statuses: &[StatusCode]) -> Self {
+        Self {
+            retryable_statuses: retryable_statuses.to_vec(),
+            ..self
+        }
+    }
+
+    /// Transport failures worth another attempt.
+    pub fn with_retryable_errors(self, retryable_errors: &[RetryableError]) -> Self {
+        Self {
+            retryable_errors: retryable_errors.to_vec(),
+            ..self
+        }
+    }
+
+    pub(crate) fn max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub(crate) fn is_retryable(&self, error: &JwksClientError) -> bool {
+        let JwksClientError::Error(error) = error;
+
+        match error.as_ref() {
+            Error::HttpStatus(status) => self.retryable_statuses.contains(status),
+            #[cfg(feature = "web-source")]
+            Error::Reqwest(error) => self.is_retryable_reqwest_error(error),
+            _ => false,
+        }
+    }
+
+    #[cfg(feature = "web-source")]
+    fn is_retryable_reqwest_error(&self, error: &reqwest::Error) -> bool {
+        if let Some(status) = error.status() {
+            return self.retryable_statuses.contains(&status);
+        }
+
+        self.retryable_errors
+            .iter()
+            .any(|retryable_error: &RetryableError| match retryable_error {
+                RetryableError::Connect => error.is_connect(),
+                RetryableError::Timeout => error.is_timeout(),
+                RetryableError::Request => error.is_request(),
+                RetryableError::Body => error.is_body(),
+            })
+    }
+
+    /// The delay to wait after the given failed attempt, starting from 1.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let factor: u32 = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay: Duration = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        with_jitter(delay, self.jitter).min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn delay_grows_exponentially_up_to_max_delay() {
+        let policy: RetryPolicy = RetryPolicy::new()
+            .with_base_delay(Duration::from_millis(100))
+            .with_max_delay(Duration::from_millis(500))
+            .with_jitter(0.0);
+
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(400), policy.delay(3));
+        assert_eq!(Duration::from_millis(500), policy.delay(4));
+        assert_eq!(Duration::from_millis(500), policy.delay(64));
+    }
+
+    #[test]
+    #[should_panic(expected = "max_attempts must be greater than 0")]
+    fn zero_max_attempts_panics() {
+        let _ = RetryPolicy::new().with_max_attempts(0);
+    }
+
+    #[test]
+    fn delay_with_jitter() {
+        let policy: RetryPolicy = RetryPolicy::new()
+            .with_base_delay(Duration::from_millis(100))
+            .with_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay: Duration = policy.delay(1);
+            assert!(delay >= Duration::from_millis(50));
+            assert!(delay <= Duration::from_millis(150));
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::{Arc, RwLock};
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+use crate::keyset::JsonWebKeySet;
+use crate::source::JwksSource;
+use crate::JwksClientError;
+
+/// Source serving a key set held in memory, e.g. in tests or when the keys are embedded in the
+/// application.
+///
+/// The keys can be replaced at runtime through a [`StaticSourceHandle`]: the client refreshes its
+/// cache right away when that happens.
+pub struct StaticSource {
+    shared: Arc<Shared>,
+}
+
+/// Replaces the keys served by the [`StaticSource`] it has been created from.
+#[derive(Clone)]
+pub struct StaticSourceHandle {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    key_set: RwLock<JsonWebKeySet>,
+    changes: watch::Sender<()>,
+}
+
+impl StaticSource {
+    pub fn new(key_set: JsonWebKeySet) -> Self {
+        let (changes, _) = watch::channel(());
+
+        Self {
+            shared: Arc::new(Shared {
+                key_set: RwLock::new(key_set),
+                changes,
+            }),
+        }
+    }
+
+    pub fn handle(&self) -> StaticSourceHandle {
+        StaticSourceHandle {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl StaticSourceHandle {
+    pub fn set_keys(&self, key_set: JsonWebKeySet) {
+        *self
+            .shared
+            .key_set
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = key_set;
+        self.shared.changes.send_replace(());
+    }
+}
+
+#[async_trait]
+impl JwksSource for StaticSource {
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        Ok(self
+            .shared
+            .key_set
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone())
+    }
+
+    fn changes(&self) -> Option<watch::Receiver<()>> {
+        Some(self.shared.changes.subscribe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::keyset::EcPublicJwk;
+    use crate::source::{JwksSource, StaticSource, StaticSourceHandle};
+    use crate::{JsonWebKeySet, JwksClient};
+
+    fn key_set(kid: &str) -> JsonWebKeySet {
+        JsonWebKeySet::new(vec![EcPublicJwk::new(
+            kid,
+            "P-256",
+            "LEBfQpwTDXJtLFiPcnYvGv-WaFXZGBnFP_yGhLL9MGc",
+            "a1Or3ovkpH12b0o3ruZUtm_z8bg3xQtHXi-uPC7UJT0",
+        )
+        .into()])
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_returns_current_keys() {
+        let source: StaticSource = StaticSource::new(key_set("old-key"));
+        let handle: StaticSourceHandle = source.handle();
+
+        assert!(source
+            .fetch_keys()
+            .await
+            .unwrap()
+            .get_key("old-key")
+            .is_ok());
+
+        handle.set_keys(key_set("new-key"));
+
+        let fetched: JsonWebKeySet = source.fetch_keys().await.unwrap();
+        assert!(fetched.get_key("old-key").is_err());
+        assert!(fetched.get_key("new-key").is_ok());
+    }
+
+    #[tokio::test]
+    async fn client_refreshes_keys_when_replaced() {
+        let source: StaticSource = StaticSource::new(key_set("old-key"));
+        let handle: StaticSourceHandle = source.handle();
+        let client: JwksClient<StaticSource> = JwksClient::builder().build(source);
+
+        assert!(client.get("old-key").await.is_ok());
+
+        handle.set_keys(key_set("new-key"));
+
+        // The old key would be served from the cache until its expiration without a refresh
+        let mut refreshed: bool = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            if client.get("old-key").await.is_err() {
+                refreshed = true;
+                break;
+            }
+        }
+
+        assert!(refreshed);
+        assert!(client.get("new-key").await.is_ok());
+    }
+}
@@ -0,0 +1,564 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use http::header::{
+    HeaderMap, AGE, CACHE_CONTROL, DATE, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use http::StatusCode;
+use url::Url;
+
+use crate::error::Error;
+use crate::keyset::JsonWebKeySet;
+use crate::source::{FetchedKeySet, HttpFetcher, JwksSource, RetryPolicy, Validators};
+use crate::JwksClientError;
+
+#[cfg(feature = "web-source")]
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+#[cfg(feature = "web-source")]
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub(crate) type DynFetcher = dyn HttpFetcher + Send + Sync;
+
+pub struct WebSource {
+    fetcher: Arc<DynFetcher>,
+    url: Url,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl WebSource {
+    pub fn builder() -> WebSourceBuilder {
+        WebSourceBuilder::new()
+    }
+
+    pub(crate) fn new(
+        fetcher: Arc<DynFetcher>,
+        url: Url,
+        retry_policy: Option<RetryPolicy>,
+    ) -> Self {
+        Self {
+            fetcher,
+            url,
+            retry_policy,
+        }
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+}
+
+#[async_trait]
+impl JwksSource for WebSource {
+    #[tracing::instrument(skip(self), fields(url = %self.url))]
+    async fn fetch_keys(&self) -> Result<JsonWebKeySet, JwksClientError> {
+        let fetched: FetchedKeySet = fetch_keys(self, None).await?;
+        // Unconditional requests never end up as "not modified"
+        Ok(fetched.into_set().unwrap_or_else(JsonWebKeySet::empty))
+    }
+
+    #[tracing::instrument(skip(self), fields(url = %self.url))]
+    async fn fetch_keys_with_freshness(&self) -> Result<FetchedKeySet, JwksClientError> {
+        fetch_keys(self, None).await
+    }
+
+    #[tracing::instrument(skip(self, validators), fields(url = %self.url))]
+    async fn fetch_keys_if_modified(
+        &self,
+        validators: &Validators,
+    ) -> Result<FetchedKeySet, JwksClientError> {
+        // Validators of a key set fetched from another URL don't tell anything about this one
+        let validators: Option<&Validators> =
+            Some(validators).filter(|validators| validators.origin() == self.url.as_str());
+
+        fetch_keys(self, validators).await
+    }
+}
+
+async fn fetch_keys(
+    source: &WebSource,
+    validators: Option<&Validators>,
+) -> Result<FetchedKeySet, JwksClientError> {
+    let max_attempts: u32 = source
+        .retry_policy
+        .as_ref()
+        .map_or(1, RetryPolicy::max_attempts);
+    let mut attempt: u32 = 1;
+
+    loop {
+        tracing::debug!(attempt, "fetching keys");
+
+        match fetch_keys_once(source, validators).await {
+            Ok(fetched) => return Ok(fetched),
+            Err(error) => match &source.retry_policy {
+                Some(retry_policy)
+                    if attempt < max_attempts && retry_policy.is_retryable(&error) =>
+                {
+                    let delay: Duration = retry_policy.delay(attempt);
+                    tracing::warn!(attempt, ?delay, %error, "fetching keys failed, retrying");
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => {
+                    tracing::warn!(attempt, %error, "fetching keys failed");
+                    return Err(error);
+                }
+            },
+        }
+    }
+}
+
+async fn fetch_keys_once(
+    source: &WebSource,
+    validators: Option<&Validators>,
+) -> Result<FetchedKeySet, JwksClientError> {
+    let mut request_builder = http::Request::get(source.url.as_str());
+
+    if let Some(validators) = validators {
+        if let Some(etag) = validators.etag() {
+            request_builder = request_builder.header(IF_NONE_MATCH, etag);
+        }
+
+        if let Some(last_modified) = validators.last_modified() {
+            request_builder = request_builder.header(IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let request: http::Request<()> = request_builder.body(()).map_err(JwksClientError::fetch)?;
+    let response: http::Response<Vec<u8>> = source.fetcher.get(request).await?;
+    let max_age: Option<Duration> = max_age(response.headers());
+
+    let fetched: FetchedKeySet = match validators {
+        Some(validators) if response.status() == StatusCode::NOT_MODIFIED => {
+            tracing::debug!("key set not modified");
+            FetchedKeySet::not_modified().with_validators(validators.clone())
+        }
+        _ if !response.status().is_success() => {
+            return Err(Error::HttpStatus(response.status()).into());
+        }
+        _ => {
+            let keys: JsonWebKeySet =
+                serde_json::from_slice(response.body()).map_err(Error::InvalidResponseBody)?;
+
+            match validators_from_headers(source.url.as_str(), response.headers()) {
+                Some(validators) => FetchedKeySet::new(keys).with_validators(validators),
+                None => FetchedKeySet::new(keys),
+            }
+        }
+    };
+
+    Ok(match max_age {
+        Some(max_age) => fetched.with_max_age(max_age),
+        None => fetched,
+    })
+}
+
+/// Reads the validators of a response, if it has any.
+fn validators_from_headers(origin: &str, headers: &HeaderMap) -> Option<Validators> {
+    let etag: Option<&str> = headers.get(ETAG).and_then(|value| value.to_str().ok());
+    let last_modified: Option<&str> = headers
+        .get(LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok());
+
+    if etag.is_none() && last_modified.is_none() {
+        return None;
+    }
+
+    let mut validators: Validators = Validators::new(origin);
+
+    if let Some(etag) = etag {
+        validators = validators.with_etag(etag);
+    }
+
+    if let Some(last_modified) = last_modified {
+        validators = validators.with_last_modified(last_modified);
+    }
+
+    Some(validators)
+}
+
+/// Reads the freshness lifetime of a response from its `Cache-Control` header, falling back to
+/// `Expires`. See <https://www.rfc-editor.org/rfc/rfc9111#section-4.2.1>
+fn max_age(headers: &HeaderMap) -> Option<Duration> {
+    let cache_control: Option<&str> = headers
+        .get(CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok());
+
+    if let Some(cache_control) = cache_control {
+        for directive in cache_control.split(',') {
+            let directive: String = directive.trim().to_ascii_lowercase();
+
+            if directive == "no-cache" || directive == "no-store" {
+                return Some(Duration::ZERO);
+            }
+
+            if let Some(seconds) = directive.strip_prefix("max-age=") {
+                if let Ok(seconds) = seconds.trim_matches('"').parse::<u64>() {
+                    let age: u64 = headers
+                        .get(AGE)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.trim().parse::<u64>().ok())
+                        .unwrap_or(0);
+
+                    return Some(Duration::from_secs(seconds.saturating_sub(age)));
+                }
+            }
+        }
+    }
+
+    let expires: DateTime<Utc> = http_date(headers.get(EXPIRES)?.to_str().ok()?)
+        // An invalid `Expires` means the response is already expired
+        .unwrap_or(DateTime::UNIX_EPOCH);
+    let date: DateTime<Utc> = headers
+        .get(DATE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(http_date)
+        .unwrap_or_else(Utc::now);
+
+    Some((expires - date).to_std().unwrap_or(Duration::ZERO))
+}
+
+fn http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Builder of a [`WebSource`]. With the `web-source` feature the keys are fetched with a
+/// `reqwest::Client`; with the `http` feature alone an [`HttpFetcher`] must be given to
+/// [`build_with_http_fetcher`](Self::build_with_http_fetcher).
+pub struct WebSourceBuilder {
+    #[cfg(feature = "web-source")]
+    client_builder: reqwest::ClientBuilder,
+    #[cfg(feature = "web-source")]
+    fetcher_opt: Option<Arc<DynFetcher>>,
+    #[cfg(feature = "web-source")]
+    timeout_opt: Option<Duration>,
+    #[cfg(feature = "web-source")]
+    connect_timeout_opt: Option<Duration>,
+    retry_policy_opt: Option<RetryPolicy>,
+}
+
+impl WebSourceBuilder {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "web-source")]
+            client_builder: reqwest::ClientBuilder::default(),
+            #[cfg(feature = "web-source")]
+            fetcher_opt: None,
+            #[cfg(feature = "web-source")]
+            timeout_opt: None,
+            #[cfg(feature = "web-source")]
+            connect_timeout_opt: None,
+            retry_policy_opt: None,
+        }
+    }
+
+    /// Fetches the keys with the given client (e.g. configured with a proxy, custom root
+    /// certificates or a client certificate) instead of building one. The timeouts of this
+    /// builder are ignored: they must be configured on the client.
+    #[cfg(feature = "web-source")]
+    pub fn with_client(self, client: reqwest::Client) -> Self {
+        self.with_http_fetcher(client)
+    }
+
+    /// Fetches the keys with the given HTTP client instead of building a `reqwest::Client`.
+    /// The timeouts of this builder are ignored: they must be configured on the fetcher.
+    #[cfg(feature = "web-source")]
+    pub fn with_http_fetcher<F: HttpFetcher + Send + Sync + 'static>(self, fetcher: F) -> Self {
+        Self {
+            fetcher_opt: Some(Arc::new(fetcher)),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "web-source")]
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        Self {
+            timeout_opt: Some(timeout),
+            ..self
+        }
+    }
+
+    #[cfg(feature = "web-source")]
+    pub fn with_connect_timeout(self, connect_timeout: Duration) -> Self {
+        Self {
+            connect_timeout_opt: Some(connect_timeout),
+            ..self
+        }
+    }
+
+    /// Retries failed fetches according to the given policy. By default a fetch is not retried.
+    ///
+    /// Lookups of a `JwksClient` needing a refresh wait for the retries, see [`RetryPolicy`].
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        // Without `web-source` this is the only field, so struct update syntax doesn't apply
+        self.retry_policy_opt = Some(retry_policy);
+        self
+    }
+
+    #[cfg(feature = "web-source")]
+    pub fn build(self, url: Url) -> Result<WebSource, reqwest::Error> {
+        let (fetcher, retry_policy) = self.build_parts()?;
+        Ok(WebSource::new(fetcher, url, retry_policy))
+    }
+
+    /// Builds the source fetching the keys with the given HTTP client, e.g. hyper or ureq
+    /// without compiling reqwest in.
+    pub fn build_with_http_fetcher<F: HttpFetcher + Send + Sync + 'static>(
+        self,
+        fetcher: F,
+        url: Url,
+    ) -> WebSource {
+        WebSource::new(Arc::new(fetcher), url, self.retry_policy_opt)
+    }
+
+    /// Builds what is shared by the sources built from this builder, that is the fetcher and the
+    /// retry policy.
+    #[cfg(feature = "web-source")]
+    pub(crate) fn build_parts(
+        self,
+    ) -> Result<(Arc<DynFetcher>, Option<RetryPolicy>), reqwest::Error> {
+        let retry_policy: Option<RetryPolicy> = self.retry_policy_opt.clone();
+        Ok((self.build_fetcher()?, retry_policy))
+    }
+
+    pub(crate) fn into_retry_policy(self) -> Option<RetryPolicy> {
+        self.retry_policy_opt
+    }
+
+    #[cfg(feature = "web-source")]
+    fn build_fetcher(self) -> Result<Arc<DynFetcher>, reqwest::Error> {
+        if let Some(fetcher) = self.fetcher_opt {
+            return Ok(fetcher);
+        }
+
+        let timeout: Duration = self.timeout_opt.unwrap_or(TIMEOUT);
+        let connect_timeout: Duration = self.connect_timeout_opt.unwrap_or(CONNECT_TIMEOUT);
+        self.client_builder
+            .timeout(timeout)
+            .connect_timeout(connect_timeout)
+            .build()
+            .map(|client: reqwest::Client| Arc::new(client) as Arc<DynFetcher>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use http::header::{HeaderMap, HeaderName, HeaderValue, AGE, CACHE_CONTROL, DATE, EXPIRES};
+    use http::StatusCode;
+    #[cfg(feature = "web-source")]
+    use httpmock::prelude::*;
+    #[cfg(feature = "web-source")]
+    use serde_json::json;
+    use url::Url;
+
+    use super::max_age;
+    use crate::source::{HttpFetcher, JwksSource, RetryPolicy, WebSource};
+    use crate::{JsonWebKeySet, JwksClientError};
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy::new()
+            .with_max_attempts(3)
+            .with_base_delay(Duration::from_millis(1))
+            .with_jitter(0.0)
+    }
+
+    #[cfg(feature = "web-source")]
+    #[tokio::test]
+    async fn fetch_keys_retries_on_retryable_status() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/keys");
+
+            then.status(502).body("Bad gateway");
+        });
+
+        let url: Url = Url::parse(&server.url("/keys")).unwrap();
+        let source: WebSource = WebSource::builder()
+            .with_retry_policy(retry_policy())
+            .build(url)
+            .unwrap();
+
+        assert!(source.fetch_keys().await.is_err());
+        mock.assert_calls(3);
+    }
+
+    #[cfg(feature = "web-source")]
+    #[tokio::test]
+    async fn fetch_keys_does_not_retry_on_client_error() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/keys");
+
+            then.status(404).body("Not found");
+        });
+
+        let url: Url = Url::parse(&server.url("/keys")).unwrap();
+        let source: WebSource = WebSource::builder()
+            .with_retry_policy(retry_policy())
+            .build(url)
+            .unwrap();
+
+        assert!(source.fetch_keys().await.is_err());
+        mock.assert_calls(1);
+    }
+
+    #[cfg(feature = "web-source")]
+    #[tokio::test]
+    async fn fetch_keys_without_retry_policy() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/keys");
+
+            then.status(502).body("Bad gateway");
+        });
+
+        let url: Url = Url::parse(&server.url("/keys")).unwrap();
+        let source: WebSource = WebSource::builder().build(url).unwrap();
+
+        assert!(source.fetch_keys().await.is_err());
+        mock.assert_calls(1);
+    }
+
+    #[cfg(feature = "web-source")]
+    #[tokio::test]
+    async fn fetch_keys_with_custom_client() {
+        let server = MockServer::start();
+
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/keys").header("x-api-key", "secret");
+
+            then.status(200).json_body(json!({ "keys": [] }));
+        });
+
+        let mut default_headers: HeaderMap = HeaderMap::new();
+        default_headers.insert("x-api-key", HeaderValue::from_static("secret"));
+        let client: reqwest::Client = reqwest::Client::builder()
+            .default_headers(default_headers)
+            .build()
+            .unwrap();
+
+        let url: Url = Url::parse(&server.url("/keys")).unwrap();
+        let source: WebSource = WebSource::builder().with_client(client).build(url).unwrap();
+
+        assert!(source.fetch_keys().await.is_ok());
+        mock.assert();
+    }
+
+    /// Fetcher failing with the given status until its last call, where it returns an empty set.
+    struct FakeFetcher {
+        status: StatusCode,
+        calls: AtomicUsize,
+        fail_calls: usize,
+    }
+
+    #[async_trait]
+    impl HttpFetcher for FakeFetcher {
+        async fn get(
+            &self,
+            request: http::Request<()>,
+        ) -> Result<http::Response<Vec<u8>>, JwksClientError> {
+            assert_eq!("https://example.com/keys", request.uri().to_string());
+
+            let status: StatusCode = if self.calls.fetch_add(1, Ordering::SeqCst) < self.fail_calls
+            {
+                self.status
+            } else {
+                StatusCode::OK
+            };
+
+            Ok(http::Response::builder()
+                .status(status)
+                .header(CACHE_CONTROL, "max-age=60")
+                .body(br#"{ "keys": [] }"#.to_vec())
+                .unwrap())
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_keys_with_custom_fetcher() {
+        let fetcher: FakeFetcher = FakeFetcher {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            calls: AtomicUsize::new(0),
+            fail_calls: 1,
+        };
+
+        let url: Url = Url::parse("https://example.com/keys").unwrap();
+        let source: WebSource = WebSource::builder()
+            .with_retry_policy(retry_policy())
+            .build_with_http_fetcher(fetcher, url);
+
+        let fetched = source.fetch_keys_with_freshness().await.unwrap();
+        assert_eq!(Some(Duration::from_secs(60)), fetched.max_age());
+        assert_eq!(
+            0,
+            fetched
+                .set()
+                .map_or(1, |set: &JsonWebKeySet| set.iter().count())
+        );
+
+        let fetcher: FakeFetcher = FakeFetcher {
+            status: StatusCode::NOT_FOUND,
+            calls: AtomicUsize::new(0),
+            fail_calls: 1,
+        };
+
+        let url: Url = Url::parse("https://example.com/keys").unwrap();
+        let source: WebSource = WebSource::builder()
+            .with_retry_policy(retry_policy())
+            .build_with_http_fetcher(fetcher, url);
+
+        assert!(source.fetch_keys().await.is_err());
+    }
+
+    fn headers(values: &[(HeaderName, &'static str)]) -> HeaderMap {
+        values
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn max_age_from_cache_control() {
+        let headers = headers(&[
+            (CACHE_CONTROL, "public, max-age=19204, must-revalidate"),
+            (AGE, "4"),
+            (EXPIRES, "Sun, 06 Nov 1994 08:49:37 GMT"),
+        ]);
+
+        assert_eq!(Some(Duration::from_secs(19200)), max_age(&headers));
+    }
+
+    #[test]
+    fn max_age_from_no_cache() {
+        let headers = headers(&[(CACHE_CONTROL, "no-cache")]);
+
+        assert_eq!(Some(Duration::ZERO), max_age(&headers));
+    }
+
+    #[test]
+    fn max_age_from_expires() {
+        let headers = headers(&[
+            (DATE, "Sun, 06 Nov 1994 08:49:37 GMT"),
+            (EXPIRES, "Sun, 06 Nov 1994 09:49:37 GMT"),
+        ]);
+
+        assert_eq!(Some(Duration::from_secs(3600)), max_age(&headers));
+    }
+
+    #[test]
+    fn max_age_without_headers() {
+        assert_eq!(None, max_age(&HeaderMap::new()));
+        assert_eq!(None, max_age(&headers(&[(CACHE_CONTROL, "public")])));
+    }
+}
@@ -0,0 +1,51 @@
+use jsonwebtoken::{Algorithm, Header};
+
+use crate::keyset::JsonWebKey;
+
+/// A token whose signature and claims have been verified, along with the header it was sent with
+/// and the key that verified it.
+#[derive(Debug, Clone)]
+pub struct VerifiedToken<O> {
+    header: Header,
+    claims: O,
+    key: JsonWebKey,
+}
+
+impl<O> VerifiedToken<O> {
+    pub(crate) fn new(header: Header, claims: O, key: JsonWebKey) -> Self {
+        Self {
+            header,
+            claims,
+            key,
+        }
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn claims(&self) -> &O {
+        &self.claims
+    }
+
+    /// The key from the key set that verified the token signature.
+    pub fn key(&self) -> &JsonWebKey {
+        &self.key
+    }
+
+    pub fn key_id(&self) -> &str {
+        self.key.key_id()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.header.alg
+    }
+
+    pub fn into_claims(self) -> O {
+        self.claims
+    }
+
+    pub fn into_parts(self) -> (Header, O, JsonWebKey) {
+        (self.header, self.claims, self.key)
+    }
+}
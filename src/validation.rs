@@ -0,0 +1,178 @@
+use std::time::Duration;
+
+use jsonwebtoken::{Algorithm, Validation};
+
+use crate::error::Error;
+use crate::keyset::JsonWebKey;
+
+const DEFAULT_LEEWAY: Duration = Duration::from_secs(60);
+
+/// Algorithms allowed by default: every asymmetric algorithm supported by `jsonwebtoken`.
+/// HMAC algorithms are never allowed since JWKS only publishes public keys.
+pub(crate) const DEFAULT_ALLOWED_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// Rules a token must satisfy to be accepted by `JwksClient::decode`, on top of a valid signature.
+///
+/// The default policy requires and validates `exp`, validates `nbf` when present and allows a
+/// leeway of 60 seconds.
+#[derive(Debug, Clone)]
+pub struct ValidationPolicy {
+    issuers: Vec<String>,
+    audience: Vec<String>,
+    leeway: Duration,
+    required_claims: Vec<String>,
+    validate_exp: bool,
+    validate_nbf: bool,
+    algorithms: Vec<Algorithm>,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            issuers: vec![],
+            audience: vec![],
+            leeway: DEFAULT_LEEWAY,
+            required_claims: vec![],
+            validate_exp: true,
+            validate_nbf: true,
+            algorithms: vec![],
+        }
+    }
+}
+
+impl ValidationPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the `iss` claim to be one of the given issuers. When empty, the issuer reported by
+    /// the source (if any) is required instead.
+    pub fn with_issuers(self, issuers: &[impl ToString]) -> Self {
+        Self {
+            issuers: issuers.iter().map(ToString::to_string).collect(),
+            ..self
+        }
+    }
+
+    /// Requires the `aud` claim, when present, to contain one of the given audience members.
+    /// Add `aud` to the required claims to reject tokens without it.
+    pub fn with_audience(self, audience: &[impl ToString]) -> Self {
+        Self {
+            audience: audience.iter().map(ToString::to_string).collect(),
+            ..self
+        }
+    }
+
+    /// Clock skew tolerated when validating `exp` and `nbf`. Precision is in seconds.
+    pub fn with_leeway(self, leeway: Duration) -> Self {
+        Self { leeway, ..self }
+    }
+
+    /// Claims that must be present in the token. Only `exp`, `nbf`, `aud`, `iss` and `sub` are
+    /// supported. `exp` is always required while its validation is enabled.
+    pub fn with_required_claims(self, required_claims: &[impl ToString]) -> Self {
+        Self {
+            required_claims: required_claims.iter().map(ToString::to_string).collect(),
+            ..self
+        }
+    }
+
+    pub fn with_validate_exp(self, validate_exp: bool) -> Self {
+        Self {
+            validate_exp,
+            ..self
+        }
+    }
+
+    pub fn with_validate_nbf(self, validate_nbf: bool) -> Self {
+        Self {
+            validate_nbf,
+            ..self
+        }
+    }
+
+    /// Restricts the algorithms the token can be signed with, on top of the client allow-list.
+    /// When empty, any algorithm allowed by the client is accepted.
+    pub fn with_algorithms(self, algorithms: &[Algorithm]) -> Self {
+        Self {
+            algorithms: algorithms.to_vec(),
+            ..self
+        }
+    }
+
+    pub(crate) fn allows_algorithm(&self, algorithm: Algorithm) -> bool {
+        self.algorithms.is_empty() || self.algorithms.contains(&algorithm)
+    }
+
+    /// Builds the `jsonwebtoken` validation for a token signed with the given algorithm, already
+    /// checked with [`check_allowed_algorithm`] and [`check_key_algorithm`].
+    /// `source_issuers` are used when the policy doesn't define any issuer.
+    pub(crate) fn to_validation(
+        &self,
+        algorithm: Algorithm,
+        source_issuers: &[String],
+    ) -> Validation {
+        let mut validation: Validation = Validation::new(algorithm);
+
+        validation.leeway = self.leeway.as_secs();
+        validation.validate_exp = self.validate_exp;
+        validation.validate_nbf = self.validate_nbf;
+
+        let mut required_claims: Vec<&str> =
+            self.required_claims.iter().map(String::as_str).collect();
+        if self.validate_exp {
+            required_claims.push("exp");
+        }
+
+        if !self.audience.is_empty() {
+            validation.set_audience(&self.audience);
+        }
+
+        // `jsonwebtoken` only checks the issuer if the claim is there, so it must be required
+        if !self.issuers.is_empty() {
+            validation.set_issuer(&self.issuers);
+            required_claims.push("iss");
+        } else if !source_issuers.is_empty() {
+            validation.set_issuer(source_issuers);
+            required_claims.push("iss");
+        }
+
+        validation.set_required_spec_claims(&required_claims);
+        validation
+    }
+}
+
+/// Checks that the token algorithm is allowed by both the client and the policy allow-lists.
+pub(crate) fn check_allowed_algorithm(
+    algorithm: Algorithm,
+    allowed_algorithms: &[Algorithm],
+    policy: &ValidationPolicy,
+) -> Result<(), Error> {
+    if allowed_algorithms.contains(&algorithm) && policy.allows_algorithm(algorithm) {
+        Ok(())
+    } else {
+        Err(Error::AlgorithmNotAllowed(algorithm))
+    }
+}
+
+/// Checks that the token algorithm can be used with the key that is going to verify it.
+pub(crate) fn check_key_algorithm(algorithm: Algorithm, key: &JsonWebKey) -> Result<(), Error> {
+    if key.supports_algorithm(algorithm) {
+        Ok(())
+    } else {
+        Err(Error::AlgorithmMismatch {
+            algorithm,
+            key_id: key.key_id().to_string(),
+        })
+    }
+}